@@ -0,0 +1,153 @@
+//! A synchronous interface for scripts and tools that don't run an async
+//! runtime themselves.
+//!
+//! ```no_run
+//! # fn main() -> atcoder::Result<()> {
+//! let client = atcoder::blocking::Client::new(atcoder::Config::default())?;
+//! let (session, _) = atcoder::blocking::Session::login("chokudai", "password", &client)?;
+//! let contest = "practice".parse()?;
+//! let (task, lang) = session.resolve_submit(&contest, "a", "rust")?;
+//! session.submit(&contest, &task, &lang, "fn main() {}".to_owned())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every call blocks the current thread on a runtime owned by the `Client`,
+//! so these must not be used from within another Tokio runtime.
+
+use std::future::Future;
+use std::sync::Arc;
+use tokio::runtime::{self, Runtime};
+
+use crate::{Authentication, Config, Result, Submission};
+use crate::ids::{ContestId, LanguageId, TaskScreenName};
+use crate::locale::Flash;
+
+/// A `crate::Client` together with the runtime its requests are driven on.
+/// Cloning is cheap and shares both.
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    pub fn new(config: Config) -> Result<Client> {
+        //! Build a client for API usage, like `create_client`.
+        let runtime = runtime::Builder::new_current_thread().enable_all().build()?;
+        Ok(Client {
+            inner: crate::create_client(config)?,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    pub fn from_async(client: &crate::Client) -> Result<Client> {
+        //! Wrap an existing client, e.g. one with a custom transport.
+        let runtime = runtime::Builder::new_current_thread().enable_all().build()?;
+        Ok(Client {
+            inner: client.clone(),
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    pub fn inner(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
+
+/// The blocking counterpart of `crate::Session`.
+#[derive(Clone)]
+pub struct Session {
+    client: Client,
+    inner: crate::Session,
+}
+
+impl Session {
+    pub fn new(client: &Client, auth: Option<Authentication>) -> Session {
+        //! Resume a session, or start an anonymous one if `auth` is `None`.
+        Session {
+            client: client.clone(),
+            inner: crate::Session::new(&client.inner, auth),
+        }
+    }
+
+    pub fn with_credentials<F>(self, provider: F) -> Session
+    where
+        F: Fn() -> Result<(String, String)> + Send + Sync + 'static,
+    {
+        //! See `crate::Session::with_credentials`.
+        Session {
+            client: self.client,
+            inner: self.inner.with_credentials(provider),
+        }
+    }
+
+    pub fn login(
+        username: &str,
+        password: &str,
+        client: &Client,
+    ) -> Result<(Session, Option<Flash>)> {
+        //! Login with username and password.
+        let (inner, message) =
+            client.block_on(crate::Session::login(username, password, &client.inner))?;
+        let session = Session {
+            client: client.clone(),
+            inner,
+        };
+        Ok((session, message))
+    }
+
+    pub fn authentication(&self) -> Option<Authentication> {
+        //! The current session cookie, e.g. for persisting it.
+        self.inner.authentication()
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn inner(&self) -> &crate::Session {
+        &self.inner
+    }
+
+    pub fn logout(&self) -> Result<Option<Flash>> {
+        //! Logout, replacing the session with an anonymous one. See
+        //! `crate::Session::logout` for its limits.
+        self.client.block_on(self.inner.logout())
+    }
+
+    pub fn join(&self, contest: &ContestId) -> Result<Option<Flash>> {
+        //! Join a contest.
+        self.client.block_on(self.inner.join(contest))
+    }
+
+    pub fn resolve_submit(
+        &self,
+        contest: &ContestId,
+        task: &str,
+        lang: &str,
+    ) -> Result<(TaskScreenName, LanguageId)> {
+        //! See `crate::Session::resolve_submit`.
+        self.client.block_on(self.inner.resolve_submit(contest, task, lang))
+    }
+
+    pub fn submit(
+        &self,
+        contest: &ContestId,
+        task: &TaskScreenName,
+        lang: &LanguageId,
+        source: String,
+    ) -> Result<Option<Flash>> {
+        //! Submit a resolution.
+        self.client.block_on(self.inner.submit(contest, task, lang, source))
+    }
+
+    pub fn submissions(&self, contest: &ContestId) -> Result<Vec<Submission>> {
+        //! List own submissions of a contest.
+        self.client.block_on(self.inner.submissions(contest))
+    }
+}
@@ -0,0 +1,228 @@
+//! An on-disk cache for pages fetched with GET, so that pages which rarely
+//! change, like task statements, aren't downloaded on every command.
+//!
+//! Entries are kept per URL and session, and follow the `Cache-Control`, `ETag` and
+//! `Last-Modified` headers of the server: a page is served from disk while
+//! it is fresh, and revalidated with a conditional request after that.
+
+use std::fs::File;
+use std::future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use reqwest::StatusCode;
+
+use crate::{session_fields, session_username, Result};
+use crate::store::write_private;
+use crate::transport::{Method, Request, Response, ResponseFuture, Transport};
+
+/// Where cached pages are kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cache {
+    pub dir: PathBuf,
+    /// Fetch every page again, even if the cached copy is still fresh. The
+    /// cache is updated with the result.
+    pub refresh: bool,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Cache {
+        Cache {
+            dir: dir.into(),
+            refresh: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Entry {
+    url: String,
+    /// The key of the session the page was fetched with.
+    session: String,
+    /// When the response was received or last revalidated, in seconds since
+    /// the Unix epoch.
+    stored: i64,
+    /// The response headers, without `Set-Cookie`.
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+#[derive(Default)]
+struct Directives {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<i64>,
+}
+
+fn directives(headers: &[(String, String)]) -> Directives {
+    let mut directives = Directives::default();
+    let values = headers.iter().filter(|(name, _)| name.eq_ignore_ascii_case("Cache-Control"));
+    for directive in values.flat_map(|(_, value)| value.split(',')) {
+        let directive = directive.trim().to_ascii_lowercase();
+        if directive == "no-store" {
+            directives.no_store = true;
+        } else if directive == "no-cache" {
+            directives.no_cache = true;
+        } else if let Some(seconds) = directive.strip_prefix("max-age=") {
+            directives.max_age = seconds.trim_matches('"').parse().ok();
+        }
+    }
+    directives
+}
+
+fn without_cookies(headers: &[(String, String)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, _)| !name.eq_ignore_ascii_case("Set-Cookie"))
+        .cloned()
+        .collect()
+}
+
+impl Entry {
+    fn fresh(&self, now: i64) -> bool {
+        let directives = directives(&self.headers);
+        !directives.no_cache && directives.max_age.is_some_and(|age| now - self.stored < age)
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &**v)
+    }
+
+    fn revalidate(&mut self, response: &Response, now: i64) {
+        //! Update the headers from a `304 Not Modified` response.
+        let updated = without_cookies(&response.headers);
+        self.headers.retain(|(name, _)| !updated.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)));
+        self.headers.extend(updated);
+        self.stored = now;
+    }
+
+    fn response(&self) -> Response {
+        Response {
+            status: StatusCode::OK,
+            headers: self.headers.clone(),
+            body: self.body.clone().into_bytes(),
+        }
+    }
+}
+
+fn now() -> i64 {
+    time::get_time().sec
+}
+
+/// 64-bit FNV-1a over each part followed by a NUL. Unlike `DefaultHasher`,
+/// it won't change with the toolchain and orphan every entry.
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for part in parts {
+        for &byte in part.as_bytes().iter().chain(&[0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+fn session_key(request: &Request) -> Option<String> {
+    //! Identifies the session a request is made with. The cookie itself
+    //! changes with every response, but its CSRF token lasts as long as the
+    //! session. Requests without one aren't cached.
+    let session = request
+        .header("Cookie")?
+        .split("; ")
+        .find_map(|cookie| cookie.strip_prefix("REVEL_SESSION="))?;
+    let fields = session_fields(session).ok()?;
+    let token = fields.get("csrf_token")?;
+    let user = session_username(&fields).unwrap_or_default();
+    Some(format!("{:016x}", fnv1a(&[&user, token])))
+}
+
+/// Passes requests on to another transport, answering GET requests from a
+/// `Cache` when possible.
+pub struct CacheTransport {
+    inner: Arc<dyn Transport>,
+    cache: Cache,
+}
+
+impl CacheTransport {
+    pub fn new(inner: Arc<dyn Transport>, cache: Cache) -> CacheTransport {
+        CacheTransport { inner, cache }
+    }
+
+    fn path(&self, url: &str, session: &str) -> PathBuf {
+        self.cache.dir.join(format!("{:016x}.json", fnv1a(&[url, session])))
+    }
+
+    fn load(&self, path: &Path, url: &str, session: &str) -> Option<Entry> {
+        // Unreadable entries are treated as missing, and replaced later.
+        let entry: Entry = serde_json::from_reader(File::open(path).ok()?).ok()?;
+        if entry.url == url && entry.session == session {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+}
+
+fn save(path: &Path, entry: &Entry) -> Result<()> {
+    // Pages of a session may hold its CSRF token, so they are kept private
+    // like the session itself.
+    write_private(path, &serde_json::to_vec(entry)?)
+}
+
+impl Transport for CacheTransport {
+    fn send(&self, mut request: Request) -> ResponseFuture {
+        let session = match session_key(&request) {
+            Some(session) if request.method == Method::Get => session,
+            _ => return self.inner.send(request),
+        };
+        let url = request.url.as_str().to_owned();
+        let path = self.path(&url, &session);
+        let cached = if self.cache.refresh {
+            None
+        } else {
+            self.load(&path, &url, &session)
+        };
+        if let Some(ref entry) = cached {
+            if entry.fresh(now()) {
+                return Box::pin(future::ready(Ok(entry.response())));
+            }
+            if let Some(etag) = entry.header("ETag") {
+                request.headers.push(("If-None-Match".to_owned(), etag.to_owned()));
+            }
+            if let Some(modified) = entry.header("Last-Modified") {
+                request.headers.push(("If-Modified-Since".to_owned(), modified.to_owned()));
+            }
+        }
+
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let response = inner.send(request).await?;
+            // Failing to update the cache only costs a download next time.
+            match cached {
+                Some(mut entry) if response.status == StatusCode::NOT_MODIFIED => {
+                    entry.revalidate(&response, now());
+                    save(&path, &entry).ok();
+                    let mut cached = entry.response();
+                    let cookies = response.header_values("Set-Cookie");
+                    cached.headers.extend(cookies.map(|v| ("Set-Cookie".to_owned(), v.to_owned())));
+                    Ok(cached)
+                }
+                _ => {
+                    if response.status == StatusCode::OK && !directives(&response.headers).no_store {
+                        let entry = Entry {
+                            url,
+                            session,
+                            stored: now(),
+                            headers: without_cookies(&response.headers),
+                            body: String::from_utf8_lossy(&response.body).into_owned(),
+                        };
+                        save(&path, &entry).ok();
+                    }
+                    Ok(response)
+                }
+            }
+        })
+    }
+}
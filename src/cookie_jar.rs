@@ -0,0 +1,164 @@
+use std::cmp::Reverse;
+use cookie::Cookie;
+use reqwest::Url;
+
+/// A cookie together with the scope it was set for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    /// Whether the cookie was set without a `Domain` attribute, and thus is
+    /// only sent to `domain` itself and not to its subdomains.
+    pub host_only: bool,
+    pub path: String,
+    /// Seconds since the Unix epoch, or `None` for a session cookie.
+    pub expires: Option<i64>,
+    pub secure: bool,
+}
+
+impl StoredCookie {
+    fn expired(&self, now: i64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or("").to_lowercase();
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_ok && path_match(url.path(), &self.path) && (!self.secure || url.scheme() == "https")
+    }
+}
+
+/// A minimal RFC 6265 cookie store.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CookieJar {
+    cookies: Vec<StoredCookie>,
+}
+
+fn now() -> i64 {
+    time::get_time().sec
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.'))
+}
+
+fn path_match(request: &str, path: &str) -> bool {
+    request == path ||
+        (request.starts_with(path) && (path.ends_with('/') || request[path.len()..].starts_with('/')))
+}
+
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(slash) => path[..slash].to_owned(),
+    }
+}
+
+impl CookieJar {
+    /// Store a cookie received in response to a request to `url`. Cookies
+    /// for an unrelated domain are ignored, and expired ones delete the
+    /// stored cookie they replace.
+    pub fn store(&mut self, cookie: &Cookie, url: &Url) {
+        let host = url.host_str().unwrap_or("").to_lowercase();
+        let (domain, host_only) = match cookie.domain() {
+            Some(domain) => {
+                let domain = domain.trim_start_matches('.').to_lowercase();
+                if !domain_match(&host, &domain) {
+                    return;
+                }
+                (domain, false)
+            }
+            None => (host, true),
+        };
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_owned(),
+            _ => default_path(url),
+        };
+        let now = now();
+        let expires = match cookie.max_age() {
+            Some(age) => Some(now + age.num_seconds()),
+            None => cookie.expires().map(|tm| tm.to_timespec().sec),
+        };
+        let stored = StoredCookie {
+            name: cookie.name().to_owned(),
+            value: cookie.value().to_owned(),
+            domain,
+            host_only,
+            path,
+            expires,
+            secure: cookie.secure().unwrap_or(false),
+        };
+        self.cookies.retain(|c| {
+            let replaced = c.name == stored.name && c.domain == stored.domain && c.path == stored.path;
+            !replaced && !c.expired(now)
+        });
+        if !stored.expired(now) {
+            self.cookies.push(stored);
+        }
+    }
+
+    /// The cookies to send with a request to `url`, most specific path
+    /// first.
+    pub fn matching(&self, url: &Url) -> Vec<&StoredCookie> {
+        let now = now();
+        let mut result: Vec<_> = self.cookies
+            .iter()
+            .filter(|c| !c.expired(now) && c.matches(url))
+            .collect();
+        result.sort_by_key(|c| Reverse(c.path.len()));
+        result
+    }
+
+    /// Look up the value of a cookie by name, regardless of its scope.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let now = now();
+        self.cookies
+            .iter()
+            .find(|c| c.name == name && !c.expired(now))
+            .map(|c| &*c.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cookie::Cookie;
+    use reqwest::Url;
+    use super::CookieJar;
+
+    #[test]
+    fn test_scope() {
+        let mut jar = CookieJar::default();
+        let url = Url::parse("https://atcoder.jp/login/").unwrap();
+        jar.store(&Cookie::parse("REVEL_SESSION=a; Path=/").unwrap(), &url);
+        jar.store(&Cookie::parse("lang=ja; Domain=.atcoder.jp; Path=/contests").unwrap(), &url);
+        jar.store(&Cookie::parse("other=x; Domain=example.com").unwrap(), &url);
+
+        let names = |url: &str| -> Vec<String> {
+            jar.matching(&Url::parse(url).unwrap())
+                .into_iter()
+                .map(|c| c.name.clone())
+                .collect()
+        };
+        assert_eq!(names("https://atcoder.jp/"), vec!["REVEL_SESSION"]);
+        assert_eq!(names("https://atcoder.jp/contests/abc001/"), vec!["lang", "REVEL_SESSION"]);
+        assert_eq!(names("https://img.atcoder.jp/contests"), vec!["lang"]);
+        assert!(names("https://example.com/").is_empty());
+    }
+
+    #[test]
+    fn test_replace_and_expire() {
+        let mut jar = CookieJar::default();
+        let url = Url::parse("https://atcoder.jp/").unwrap();
+        jar.store(&Cookie::parse("REVEL_FLASH=a; Path=/").unwrap(), &url);
+        jar.store(&Cookie::parse("REVEL_FLASH=b; Path=/").unwrap(), &url);
+        assert_eq!(jar.get("REVEL_FLASH"), Some("b"));
+        jar.store(&Cookie::parse("REVEL_FLASH=; Path=/; Max-Age=0").unwrap(), &url);
+        assert_eq!(jar.get("REVEL_FLASH"), None);
+    }
+}
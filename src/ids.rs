@@ -0,0 +1,117 @@
+//! Identifiers of contests, tasks, languages and submissions, as they
+//! appear in URLs and form fields.
+//!
+//! Each is checked to only contain the characters AtCoder uses for it, so it
+//! can be put in a URL as is. They are serialized as plain strings.
+//!
+//! Contests, tasks and submissions know the path of their page, which
+//! `Client::url` resolves against the base URL.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, ErrorKind, Result};
+
+fn is_name(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+fn is_number(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit())
+}
+
+macro_rules! id {
+    ($(#[$attr:meta])* $name:ident, $what:expr, $valid:expr) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = Error;
+
+            fn try_from(s: String) -> Result<$name> {
+                if s.is_empty() || !$valid(&s) {
+                    bail!(ErrorKind::InvalidId($what, s));
+                }
+                Ok($name(s))
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<$name> {
+                $name::try_from(s.to_owned())
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(id: $name) -> String {
+                id.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+    };
+}
+
+id!(
+    /// The name of a contest in its URL, e.g. `abc001` or `practice`.
+    ContestId, "contest ID", is_name
+);
+
+id!(
+    /// The name of a task in its URL, e.g. `abc001_1`. This is also what
+    /// the submit form identifies the task with.
+    TaskScreenName, "task screen name", is_name
+);
+
+id!(
+    /// The number the submit form identifies a language with, e.g. `5054`.
+    LanguageId, "language ID", is_number
+);
+
+id!(
+    /// The number of a submission, unique across contests.
+    SubmissionId, "submission ID", is_number
+);
+
+impl ContestId {
+    pub fn path(&self) -> String {
+        //! The contest's top page, e.g. `/contests/abc001/`. Pass paths to
+        //! `Client::url` to get the full URL.
+        self.page_path("")
+    }
+
+    pub fn page_path(&self, page: &str) -> String {
+        //! A page of the contest, e.g. `submit/`.
+        format!("/contests/{}/{}", self, page)
+    }
+}
+
+impl TaskScreenName {
+    pub fn path(&self, contest: &ContestId) -> String {
+        //! The task statement. Tasks may be shared between contests, so the
+        //! one to show it in is needed too.
+        contest.page_path(&format!("tasks/{}", self))
+    }
+}
+
+impl SubmissionId {
+    pub fn path(&self, contest: &ContestId) -> String {
+        //! The submission's detail page.
+        contest.page_path(&format!("submissions/{}", self))
+    }
+}
@@ -1,45 +1,73 @@
-#![feature(conservative_impl_trait)]
-
-extern crate cookie;
 #[macro_use]
 extern crate error_chain;
-extern crate futures;
-extern crate percent_encoding;
-extern crate reqwest;
-extern crate select;
-extern crate serde;
+#[macro_use]
+extern crate log;
 #[macro_use]
 extern crate serde_derive;
-extern crate tokio_core;
-
-mod revel_deserialize;
 
+pub mod blocking;
+pub mod cache;
+mod cookie_jar;
+pub mod ids;
+pub mod locale;
+pub mod rate_limit;
+pub mod retry;
+pub mod revel_deserialize;
+pub mod revel_serialize;
+pub mod scrape;
+pub mod store;
+pub mod trace;
+pub mod transport;
+pub mod vcr;
+
+use std::collections::HashMap;
 use std::fmt;
-use futures::{future, Future, Stream};
-use tokio_core::reactor::Handle;
-use reqwest::unstable::async::{Client, Chunk};
-use reqwest::header::{Cookie, SetCookie};
-use reqwest::{RedirectPolicy, StatusCode};
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::str::{self, FromStr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use reqwest::Client as HttpClient;
+use reqwest::redirect::Policy as RedirectPolicy;
+use reqwest::{Certificate, Proxy, StatusCode, Url};
 use cookie::Cookie as CookieParser;
 use select::document::Document;
 use select::node::Node;
 use select::predicate::{Attr, Element, Name, Text, Predicate};
 
-use revel_deserialize::RevelFlash;
+use crate::cache::{Cache, CacheTransport};
+use crate::cookie_jar::CookieJar;
+use crate::ids::{ContestId, LanguageId, SubmissionId, TaskScreenName};
+use crate::locale::{Flash, Locale, LOCALE_COOKIE};
+use crate::rate_limit::{RateLimit, RateLimitTransport, RateLimiter};
+use crate::retry::{RetryPolicy, RetryTransport};
+use crate::revel_deserialize::RevelFlash;
+use crate::scrape::{Page, ScrapeError};
+use crate::trace::TraceTransport;
+use crate::transport::{Method, ReqwestTransport, Request, Response, Transport};
+use crate::vcr::{RecordingTransport, ReplayTransport};
 
-const API_BASE: &str = "https://beta.atcoder.jp";
+/// The origin used by `Config::default()`.
+pub const DEFAULT_BASE_URL: &str = "https://atcoder.jp";
+
+/// The `User-Agent` used by `Config::default()`.
+pub const DEFAULT_USER_AGENT: &str = concat!("atcoder-rs/", env!("CARGO_PKG_VERSION"));
 
 error_chain! {
     foreign_links {
-        ReqError(::reqwest::Error);
-        CookieError(::cookie::ParseError);
-        NumError(::std::num::ParseIntError);
+        ReqError(reqwest::Error);
+        CookieError(cookie::ParseError);
+        UrlError(url::ParseError);
+        IoError(std::io::Error);
+        JsonError(serde_json::Error);
+        NumError(std::num::ParseIntError);
     }
 
     errors {
-        Unauthorized(m: String) {
+        Unauthorized(f: Flash) {
             description("Authentication failed")
-            display("Authentication failed: {}", m)
+            display("Authentication failed: {}", f)
         }
 
         BadStatus(c: StatusCode) {
@@ -52,22 +80,253 @@ error_chain! {
             display("Unexpected response from server: {}", m)
         }
 
+        InvalidSession(m: String) {
+            description("Malformed session cookie")
+            display("Malformed session cookie: {}", m)
+        }
+
+        Scrape(e: ScrapeError) {
+            description("Unexpected page layout")
+            display("Unexpected page layout: {}", e)
+        }
+
+        SessionExpired {
+            description("Session expired")
+            display("Session expired, login again")
+        }
+
+        StoreEncrypted {
+            description("The store is encrypted")
+            display("The store is encrypted, a passphrase or key file is needed")
+        }
+
+        WrongKey {
+            description("Failed to decrypt the store")
+            display("Failed to decrypt the store, the passphrase or key file is wrong")
+        }
+
+        InsecurePermissions(path: PathBuf, mode: u32) {
+            description("Other users can access the store")
+            display("Other users can access {} (mode {:o})", path.display(), mode)
+        }
+
+        InvalidId(kind: &'static str, value: String) {
+            description("Malformed identifier")
+            display("Malformed {}: {:?}", kind, value)
+        }
+
+        CassetteMismatch(m: String) {
+            description("Request doesn't match the cassette")
+            display("Request doesn't match the cassette: {}", m)
+        }
+
         NoSuchTask {
-            description("No task matched supplied prefix")
+            description("No such task on the submit page")
         }
 
         NoSuchLanguage {
-            description("No language matched supplied prefix")
+            description("No such language for the task")
+        }
+    }
+}
+
+/// Whether requests go to the network, and whether they are recorded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mode {
+    Live,
+    /// Send requests, saving every exchange with cookies and passwords
+    /// scrubbed to the given cassette file. Pages served by the cache are
+    /// saved as if they had been fetched.
+    Record(PathBuf),
+    /// Serve the exchanges saved in the given cassette file, in order,
+    /// without touching the network.
+    Replay(PathBuf),
+}
+
+/// Settings shared by every request made through a `Client`.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The origin every endpoint is resolved against, e.g.
+    /// `https://atcoder.jp`. A trailing slash is ignored.
+    pub base_url: String,
+    pub mode: Mode,
+    /// How requests failing with gateway errors or timeouts are retried.
+    pub retry: RetryPolicy,
+    /// The request rate allowed across every clone of a `Client`, including
+    /// retries. `None` disables limiting.
+    pub rate_limit: Option<RateLimit>,
+    /// Save pages that fail to scrape to the temporary directory, and
+    /// report where in `ScrapeError::dump`.
+    pub dump_html: bool,
+    /// Send every request through this proxy, e.g.
+    /// `http://proxy.example:8080`.
+    pub proxy: Option<String>,
+    /// PEM files with root certificates to trust besides the system ones.
+    pub root_certificates: Vec<PathBuf>,
+    /// Give up connecting to the server, or to the proxy, after this long.
+    pub connect_timeout: Option<Duration>,
+    /// Give up when the server has sent nothing for this long, while
+    /// waiting for either the headers or the rest of the body.
+    pub read_timeout: Option<Duration>,
+    /// The `User-Agent` sent with every request.
+    pub user_agent: String,
+    /// The language to request pages and messages in, through the `lang`
+    /// query parameter and cookie. `None` leaves it to the server.
+    pub locale: Option<Locale>,
+    /// Keep pages fetched with GET on disk, and reuse them as their
+    /// `Cache-Control` and validators allow. `None` disables caching.
+    pub cache: Option<Cache>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            mode: Mode::Live,
+            retry: RetryPolicy::default(),
+            rate_limit: Some(RateLimit::default()),
+            dump_html: false,
+            proxy: None,
+            root_certificates: vec![],
+            connect_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Some(Duration::from_secs(60)),
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            locale: None,
+            cache: None,
+        }
+    }
+}
+
+/// A HTTP client bound to a `Config`. Cloning is cheap and shares the
+/// underlying transport.
+#[derive(Clone)]
+pub struct Client {
+    transport: Arc<dyn Transport>,
+    limiter: Option<Arc<RateLimiter>>,
+    config: Config,
+}
+
+impl Client {
+    /// The configuration this client was built with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn rate_limit_wait(&self) -> Duration {
+        //! How long a request made now would be delayed by the rate limit.
+        self.limiter
+            .as_ref()
+            .map_or(Duration::new(0, 0), |limiter| limiter.wait())
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        //! Resolve `path`, which may have a query, against the base URL, asking
+        //! for pages in the configured locale.
+        let url = format!("{}{}", self.config.base_url, path);
+        match (self.config.locale, Url::parse(&url)) {
+            (Some(locale), Ok(mut parsed)) => {
+                parsed.query_pairs_mut().append_pair("lang", locale.code());
+                parsed.into()
+            }
+            // Invalid URLs are reported when a request is made with them.
+            _ => url,
         }
     }
 }
 
-/// The session returned from the server. This wraps the server-side
+/// The session returned from the server, kept as every cookie it has set
+/// along with their domain, path and expiry. This wraps the server-side
 /// implementation details to allow the storage to change from signed
 /// cookies to a more robust one.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Authentication {
-    session: String,
+    cookies: CookieJar,
+}
+
+/// The fields of a session cookie, decoded without verifying the signature
+/// and therefore not to be trusted for anything beyond display purposes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionInfo {
+    /// Screen name of the logged in user, or `None` for an anonymous session.
+    pub username: Option<String>,
+    /// When the server stops accepting the session, in seconds since the
+    /// Unix epoch.
+    pub expires: Option<i64>,
+    /// Every field in the cookie, including the ones above.
+    pub fields: HashMap<String, String>,
+}
+
+impl Authentication {
+    pub fn session_info(&self) -> Result<SessionInfo> {
+        //! Decode the user identity and expiry from the session cookie. This
+        //! doesn't touch the network.
+        //! # Server-side implementation details
+        //! Revel stores the session as `signature-payload`, where the payload
+        //! uses the same encoding as `REVEL_FLASH`.
+        let session = self.cookies.get("REVEL_SESSION").ok_or_else(|| {
+            ErrorKind::InvalidSession("No \"REVEL_SESSION\" cookie found".to_owned())
+        })?;
+        let fields = session_fields(session)?;
+        let username = session_username(&fields);
+        let expires = match fields.get("_TS") {
+            Some(ts) => Some(ts.parse()?),
+            None => None,
+        };
+        Ok(SessionInfo { username, expires, fields })
+    }
+
+    fn cookie_header(&self, url: &Url, locale: Option<Locale>) -> Option<(String, String)> {
+        //! The cookies to send to `url`, with the language cookie replaced
+        //! if `locale` is set.
+        let mut cookies: Vec<_> = self.cookies
+            .matching(url)
+            .iter()
+            .filter(|c| locale.is_none() || c.name != LOCALE_COOKIE)
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        if let Some(locale) = locale {
+            cookies.push(format!("{}={}", LOCALE_COOKIE, locale));
+        }
+        if cookies.is_empty() {
+            return None;
+        }
+        Some(("Cookie".to_owned(), cookies.join("; ")))
+    }
+
+    /// Store every cookie from a response, returning them for inspection.
+    fn receive(&mut self, url: &Url, response: &Response) -> Result<Vec<CookieParser<'static>>> {
+        let mut received = vec![];
+        for raw_cookie in response.header_values("Set-Cookie") {
+            let cookie = CookieParser::parse(raw_cookie.to_owned()).chain_err(|| {
+                ErrorKind::InvalidResponse("Failed to parse cookie".to_owned())
+            })?;
+            self.cookies.store(&cookie, url);
+            received.push(cookie);
+        }
+        ensure!(
+            self.cookies.get("REVEL_SESSION").is_some(),
+            ErrorKind::InvalidResponse("No \"REVEL_SESSION\" cookie found".to_owned())
+        );
+        Ok(received)
+    }
+}
+
+pub(crate) fn session_fields(session: &str) -> Result<HashMap<String, String>> {
+    //! Decode the value of a `REVEL_SESSION` cookie, ignoring its signature.
+    let payload = &session[session.find('-').ok_or_else(|| {
+        ErrorKind::InvalidSession("No signature found".to_owned())
+    })? + 1..];
+    revel_deserialize::from_bytes(payload.as_bytes()).map_err(|e| {
+        let message = format!("Failed to decode payload: {}", e);
+        Error::with_chain(e, ErrorKind::InvalidSession(message))
+    })
+}
+
+pub(crate) fn session_username(fields: &HashMap<String, String>) -> Option<String> {
+    fields
+        .get("UserScreenName")
+        .or_else(|| fields.get("UserName"))
+        .cloned()
 }
 
 fn csrf_token(document: &Document) -> Option<String> {
@@ -85,225 +344,293 @@ fn csrf_token(document: &Document) -> Option<String> {
     }
 }
 
-fn get_api(
-    endpoint: String,
+fn redirected_to_login(url: &Url, response: &Response) -> bool {
+    response
+        .header("Location")
+        .and_then(|location| url.join(location).ok())
+        .is_some_and(|target| target.path().starts_with("/login"))
+}
+
+fn encode_form(form: &[(&str, String)]) -> String {
+    let mut body = String::new();
+    for &(name, ref value) in form {
+        if !body.is_empty() {
+            body.push('&');
+        }
+        revel_serialize::escape(name, &mut body);
+        body.push('=');
+        revel_serialize::escape(value, &mut body);
+    }
+    body
+}
+
+async fn get_api(
+    endpoint: &str,
     auth: Option<Authentication>,
     client: &Client,
-) -> impl Future<Item=(Authentication, Chunk), Error=Error> {
-    future::lazy({
-        let client = client.clone();
-        move || -> Result<_> {
-            let mut request = client.get(&endpoint);
-            if let Some(auth) = auth {
-                let mut cookie = Cookie::new();
-                cookie.append("REVEL_SESSION", auth.session);
-                request.header(cookie);
-            }
-            Ok(request.send().from_err())
-        }
-    }).flatten()
-        .and_then(|response| -> Result<_> {
-            ensure!(
-                response.status() == StatusCode::Ok,
-                ErrorKind::BadStatus(response.status())
-            );
-            let cookies = response.headers().get::<SetCookie>().cloned().ok_or_else(||
-                                                                                        ErrorKind::InvalidResponse("No cookies received".to_owned()),
-            )?;
-            for raw_cookie in &**cookies {
-                let cookie = CookieParser::parse(&**raw_cookie).chain_err(|| {
-                    ErrorKind::InvalidResponse("Failed to parse cookie".to_owned())
-                })?;
-                if cookie.name() == "REVEL_SESSION" {
-                    return Ok((
-                        Authentication { session: cookie.value().to_owned() },
-                        response,
-                    ));
-                }
-            }
-            bail!(ErrorKind::InvalidResponse(
-                "No \"REVEL_SESSION\" cookie found".to_owned(),
-            ));
-        })
-        .and_then(|(auth, response)| {
-            future::ok(auth).join(response.into_body().concat2().from_err())
-        })
+) -> Result<(Authentication, Vec<u8>)> {
+    let url = Url::parse(endpoint)?;
+    let mut auth = auth.unwrap_or_default();
+    let request = Request {
+        method: Method::Get,
+        url: url.clone(),
+        headers: auth.cookie_header(&url, client.config.locale).into_iter().collect(),
+        body: None,
+    };
+    let response = client.transport.send(request).await?;
+    if response.status == StatusCode::FOUND && redirected_to_login(&url, &response) {
+        bail!(ErrorKind::SessionExpired);
+    }
+    ensure!(
+        response.status == StatusCode::OK,
+        ErrorKind::BadStatus(response.status)
+    );
+    auth.receive(&url, &response)?;
+    Ok((auth, response.body))
 }
 
-fn get_post<F: FnOnce(&Document) -> Result<Vec<(&'static str, String)>> + 'static>(
+async fn get_post<F: FnOnce(&Document) -> Result<Vec<(&'static str, String)>>>(
     get: String,
     post: Option<String>,
     form_data: F,
     auth: Option<Authentication>,
     client: &Client,
-) -> impl Future<Item=(Option<String>, Authentication), Error=Error> + 'static {
-    let post = post.unwrap_or(get.clone());
-    get_api(get, auth, client)
-        .and_then(move |(auth, body)| {
-            let document = Document::from(::std::str::from_utf8(&body).chain_err(|| {
-                ErrorKind::InvalidResponse("Cannot decode response".to_owned())
-            })?);
-            let mut form = form_data(&document)?;
-            form.push((
-                "csrf_token",
-                csrf_token(&document).ok_or_else(|| ErrorKind::InvalidResponse(
-                    "Cannot find csrf_token".to_owned(),
-                ))?,
-            ));
-            Ok((auth, form))
-        })
-        .and_then({
-            let client = client.clone();
-            move |(auth, form)| {
-                let mut cookie = Cookie::new();
-                cookie.append("REVEL_SESSION", auth.session);
-                let mut request = client.post(&post);
-                request.header(cookie);
-                request.form(&form);
-                Ok(request.send().from_err())
+) -> Result<(Option<Flash>, Authentication)> {
+    let post = post.unwrap_or_else(|| get.clone());
+    let (mut auth, body) = get_api(&get, auth, client).await?;
+    // The document can't be sent across threads, so it must be gone before
+    // the next request.
+    let form = {
+        let html = str::from_utf8(&body).chain_err(|| {
+            ErrorKind::InvalidResponse("Cannot decode response".to_owned())
+        })?;
+        let document = Document::from(html);
+        let mut form = form_data(&document)?;
+        let page = Page { html, url: Some(&get), dump: client.config.dump_html };
+        form.push((
+            "csrf_token",
+            csrf_token(&document).ok_or_else(|| page.error(ScrapeError::new(
+                "input[name=csrf_token]",
+                "a single CSRF token",
+            )))?,
+        ));
+        form
+    };
+
+    let url = Url::parse(&post)?;
+    let mut headers = vec![(
+        "Content-Type".to_owned(),
+        "application/x-www-form-urlencoded".to_owned(),
+    )];
+    headers.extend(auth.cookie_header(&url, client.config.locale));
+    let request = Request {
+        method: Method::Post,
+        url: url.clone(),
+        headers,
+        body: Some(encode_form(&form)),
+    };
+    let response = client.transport.send(request).await?;
+    ensure!(
+        response.status == StatusCode::FOUND,
+        ErrorKind::BadStatus(response.status)
+    );
+    let mut success = None;
+    for cookie in auth.receive(&url, &response)? {
+        if cookie.name() == "REVEL_FLASH" {
+            let flash: RevelFlash = revel_deserialize::from_bytes(
+                cookie.value().as_bytes(),
+            ).map_err(|e| {
+                let message = format!("Failed to decode \"REVEL_FLASH\": {}", e);
+                Error::with_chain(e, ErrorKind::InvalidResponse(message))
+            })?;
+            if let Some(err) = flash.error {
+                bail!(ErrorKind::Unauthorized(Flash::new(err)))
+            } else {
+                success = flash.success.map(Flash::new);
             }
-        })
-        .flatten()
-        .and_then(|response| {
-            ensure!(
-                response.status() == StatusCode::Found,
-                ErrorKind::BadStatus(response.status())
-            );
-            let cookies = response.headers().get::<SetCookie>().ok_or_else(||
-                                                                               ErrorKind::InvalidResponse("No cookie received".to_owned()),
-            )?;
-            let mut result = None;
-            let mut success = None;
-            for raw_cookie in &**cookies {
-                let cookie = CookieParser::parse(&**raw_cookie).chain_err(|| {
-                    ErrorKind::InvalidResponse("Failed to parse cookie".to_owned())
-                })?;
-                if cookie.name() == "REVEL_SESSION" {
-                    result = Some(Authentication { session: cookie.value().to_owned() });
-                }
-                if cookie.name() == "REVEL_FLASH" {
-                    let flash: RevelFlash = revel_deserialize::from_bytes(
-                        cookie.value().as_bytes(),
-                    ).chain_err(|| {
-                        ErrorKind::InvalidResponse("Failed to decode \"REVEL_FLASH\"".to_owned())
-                    })?;
-                    if let Some(err) = flash.error {
-                        bail!(ErrorKind::Unauthorized(err))
-                    } else {
-                        success = flash.success;
-                    }
-                }
+        }
+    }
+    // Checked after the flash, as a wrong password also leads back to the
+    // login page. Without one, the form was rejected for the session alone.
+    if redirected_to_login(&url, &response) {
+        bail!(ErrorKind::SessionExpired);
+    }
+    Ok((success, auth))
+}
+
+pub fn create_client(config: Config) -> Result<Client> {
+    //! Build a client for API usage. Requests must be made from within a
+    //! Tokio runtime.
+    let mut limiter = None;
+    let transport: Arc<dyn Transport> = match config.mode {
+        Mode::Replay(ref path) => {
+            Arc::new(TraceTransport::new(Arc::new(ReplayTransport::load(path)?)))
+        }
+        ref mode => {
+            let http = http_client(&config)?;
+            let reqwest = ReqwestTransport::new(http).with_read_timeout(config.read_timeout);
+            let mut live: Arc<dyn Transport> = Arc::new(TraceTransport::new(Arc::new(reqwest)));
+            if let Some(ref limit) = config.rate_limit {
+                let shared = Arc::new(RateLimiter::new(limit.clone()));
+                live = Arc::new(RateLimitTransport::new(live, shared.clone()));
+                limiter = Some(shared);
             }
-            result
-                .ok_or_else(||
-                                ErrorKind::InvalidResponse("No \"REVEL_SESSION\" cookie found".to_owned())
-                                    .into(),
-                )
-                .map(|auth| (success, auth))
-        })
+            if let Some(ref cache) = config.cache {
+                live = Arc::new(CacheTransport::new(live, cache.clone()));
+            }
+            // Recording sits outside the cache, so that pages served from
+            // disk are saved too, and revalidated ones as full responses:
+            // replaying builds no cache.
+            match *mode {
+                Mode::Record(ref path) => Arc::new(RecordingTransport::new(live, path.clone())),
+                _ => live,
+            }
+        }
+    };
+    let transport = Arc::new(RetryTransport::new(transport, config.retry.clone()));
+    let mut client = create_client_with_transport(config, transport);
+    client.limiter = limiter;
+    Ok(client)
 }
 
-pub fn create_client(handle: &Handle) -> Result<Client> {
-    //! Build a reqwest client for API usage.
-    Client::builder()
+fn http_client(config: &Config) -> Result<HttpClient> {
+    let mut builder = HttpClient::builder()
         .redirect(RedirectPolicy::none())
-        .build(handle)
-        .map_err(Error::from)
+        .user_agent(&*config.user_agent);
+    if let Some(ref proxy) = config.proxy {
+        builder = builder.proxy(Proxy::all(&**proxy)?);
+    }
+    for path in &config.root_certificates {
+        let pem = fs::read(path)
+            .chain_err(|| format!("Failed to read root certificate {}", path.display()))?;
+        let certificate = Certificate::from_pem(&pem)
+            .chain_err(|| format!("Invalid root certificate {}", path.display()))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if let Some(timeout) = config.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    Ok(builder.build()?)
+}
+
+pub fn create_client_with_transport(mut config: Config, transport: Arc<dyn Transport>) -> Client {
+    //! Build a client that sends its requests through `transport`, e.g. a
+    //! `MemoryTransport` in tests. The transport is used as is, so
+    //! `config.retry` and `config.rate_limit` only apply if it is a
+    //! `RetryTransport` or `RateLimitTransport`.
+    let trimmed = config.base_url.trim_end_matches('/').len();
+    config.base_url.truncate(trimmed);
+    Client { transport, limiter: None, config }
 }
 
-pub fn login(
+async fn login(
     username: &str,
     password: &str,
     client: &Client,
-) -> impl Future<Item=(Authentication, Option<String>), Error=Error> {
-    //! Login with username and password.
+) -> Result<(Option<Flash>, Authentication)> {
     let form = vec![
         ("username", username.to_owned()),
         ("password", password.to_owned()),
     ];
     get_post(
-        format!("{}/login/", API_BASE),
+        client.url("/login/"),
         None,
         move |_| Ok(form),
         None,
         client,
-    ).map(|(message, auth)| (auth, message))
+    ).await
 }
 
-pub fn logout(
-    auth: Authentication,
+async fn logout(
+    auth: Option<Authentication>,
     client: &Client,
-) -> impl Future<Item=Option<String>, Error=Error> {
-    //! Logout, making the current `Authentication` no longer usable.
-    //! # Server-side implementation details
-    //! The server framework, Revel, currently doesn't store sessions in
-    //! database, and thus has no ability to invalidate a token other than
-    //! timing out. Thus, this cannot be used for safety purposes.
+) -> Result<(Option<Flash>, Authentication)> {
     get_post(
-        format!("{}", API_BASE),
-        Some(format!("{}/logout/", API_BASE)),
+        client.url("/"),
+        Some(client.url("/logout/")),
         |_| Ok(vec![]),
-        Some(auth),
+        auth,
         client,
-    ).map(|(message, _)| message)
+    ).await
 }
 
-pub fn join(
-    contest: &str,
-    auth: Authentication,
+async fn join(
+    contest: &ContestId,
+    auth: Option<Authentication>,
     client: &Client,
-) -> impl Future<Item=(Option<String>, Authentication), Error=Error> {
-    //! Join a contest.
+) -> Result<(Option<Flash>, Authentication)> {
     get_post(
-        format!("{}/contests/{}/", API_BASE, contest),
-        Some(format!("{}/contests/{}/register/", API_BASE, contest)),
+        client.url(&contest.path()),
+        Some(client.url(&contest.page_path("register/"))),
         |_| Ok(vec![]),
-        Some(auth),
+        auth,
         client,
-    )
+    ).await
+}
+
+fn find_option<'a, P>(doc: &'a Document, select: &str, predicate: P) -> Option<&'a str>
+where
+    P: Fn(&Node<'a>) -> bool,
+{
+    doc.find(Attr("id", select).descendant(Name("option")))
+        .find(predicate)
+        .and_then(|n| n.attr("value"))
 }
 
-pub fn submit(
-    contest: &str,
+async fn resolve_submit(
+    contest: &ContestId,
     task: &str,
     lang: &str,
+    auth: Option<Authentication>,
+    client: &Client,
+) -> Result<((TaskScreenName, LanguageId), Authentication)> {
+    let endpoint = client.url(&contest.page_path("submit/"));
+    let (auth, body) = get_api(&endpoint, auth, client).await?;
+    let html = str::from_utf8(&body).chain_err(|| {
+        ErrorKind::InvalidResponse("Cannot decode response".to_owned())
+    })?;
+    let doc = Document::from(html);
+    let task = task.to_lowercase();
+    let lang = lang.to_lowercase();
+    let task_id = find_option(&doc, "select-task", |t| {
+        t.inner_html().to_lowercase().starts_with(&task)
+    }).ok_or(ErrorKind::NoSuchTask)?;
+    let select_lang = format!("select-lang-{}", task_id);
+    let lang_id = find_option(&doc, &select_lang, |t| {
+        t.inner_html().to_lowercase().starts_with(&lang)
+    }).ok_or(ErrorKind::NoSuchLanguage)?;
+    Ok(((task_id.parse()?, lang_id.parse()?), auth))
+}
+
+async fn submit(
+    contest: &ContestId,
+    task: &TaskScreenName,
+    lang: &LanguageId,
     source: String,
-    auth: Authentication,
+    auth: Option<Authentication>,
     client: &Client,
-) -> impl Future<Item=(Option<String>, Authentication), Error=Error> {
-    //! Submit a resolution.
-    //! The `task` and `lang` parameters are patterns, and are matched against
-    //! the start of the options.
+) -> Result<(Option<Flash>, Authentication)> {
     get_post(
-        format!("{}/contests/{}/submit/", API_BASE, contest),
+        client.url(&contest.page_path("submit/")),
         None,
-        {
-            let task = task.to_lowercase();
-            let lang = lang.to_lowercase();
-            move |doc| {
-                let mut tasks = doc.find(Attr("id", "select-task").descendant(Name("option")));
-                let task_id = tasks
-                    .find(|t| t.inner_html().to_lowercase().starts_with(&task))
-                    .and_then(|n| n.attr("value"))
-                    .ok_or_else(|| ErrorKind::NoSuchTask)?;
-                let select_lang = format!("select-lang-{}", task_id);
-                let mut langs = doc.find(Attr("id", &*select_lang).descendant(Name("option")));
-                let lang_id = langs
-                    .find(|t| t.inner_html().to_lowercase().starts_with(&lang))
-                    .and_then(|n| n.attr("value"))
-                    .ok_or_else(|| ErrorKind::NoSuchLanguage)?;
-                Ok(vec![
-                    ("data.TaskScreenName", task_id.to_owned()),
-                    ("data.LanguageId", lang_id.to_owned()),
-                    ("sourceCode", source),
-                ])
-            }
+        move |doc| {
+            find_option(doc, "select-task", |t| t.attr("value") == Some(task.as_str()))
+                .ok_or(ErrorKind::NoSuchTask)?;
+            let select_lang = format!("select-lang-{}", task);
+            find_option(doc, &select_lang, |t| t.attr("value") == Some(lang.as_str()))
+                .ok_or(ErrorKind::NoSuchLanguage)?;
+            Ok(vec![
+                ("data.TaskScreenName", task.to_string()),
+                ("data.LanguageId", lang.to_string()),
+                ("sourceCode", source),
+            ])
         },
-        Some(auth),
+        auth,
         client,
-    )
+    ).await
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum SubmissionResult {
     Pass,
     Fail,
@@ -312,6 +639,7 @@ pub enum SubmissionResult {
     CompileError,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum SubmissionStatus {
     Pending,
     InProgress {
@@ -346,8 +674,9 @@ impl fmt::Display for SubmissionStatus {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Submission {
-    pub id: String,
+    pub id: SubmissionId,
     pub timestamp: String,
     pub task: String,
     pub user: String,
@@ -362,136 +691,302 @@ pub struct Submission {
 }
 
 // TODO: filter and all submissions
-pub fn submissions(
-    contest: &str,
+async fn submissions(
+    contest: &ContestId,
     auth: Option<Authentication>,
     client: &Client,
-) -> impl Future<Item=(Vec<Submission>, Authentication), Error=Error> {
-    get_api(
-        format!("{}/contests/{}/submissions/me/", API_BASE, contest),
-        auth,
-        client,
-    ).and_then(|(auth, body)| {
-        let document = Document::from(::std::str::from_utf8(&body).chain_err(|| {
-            ErrorKind::InvalidResponse("Cannot decode response".to_owned())
-        })?);
-        let result_tbody = document
-            .find(Name("table").descendant(Name("tbody")))
-            .next()
-            .ok_or_else(|| ErrorKind::InvalidResponse(
-                "No result table found".to_owned(),
-            ))?;
-        let results = result_tbody.children().filter(|e| e.is(Element)).map(|row| {
-            fn next_text<'a, T: Iterator<Item=Node<'a>>>(iter: &mut T) -> Result<&'a str> {
-                Ok(iter.next()
-                    .ok_or_else(|| ErrorKind::InvalidResponse(
-                        "Table layout mismatch".to_owned(),
-                    ))?
-                    .find(Text)
-                    .map(|e| e.as_text().unwrap().trim())
-                    .filter(|s| !s.is_empty())
-                    .next()
-                    .ok_or_else(|| ErrorKind::InvalidResponse(
-                        "Table layout mismatch".to_owned(),
-                    ))?)
-            }
-            let mut col_iter = row.children().filter(|e| e.is(Name("td")));
-            let timestamp = next_text(&mut col_iter)?.to_owned();
-            // TODO: chrono parse
-            let task = next_text(&mut col_iter)?.to_owned();
-            // TODO: internal id
-            let user = next_text(&mut col_iter)?.to_owned();
-            let lang = next_text(&mut col_iter)?.to_owned();
-            let score = next_text(&mut col_iter)?.parse()?;
-            let code_length_str = next_text(&mut col_iter)?;
-            let byte_pattern = " Byte";
-            if !code_length_str.ends_with(byte_pattern) {
-                return Err(
-                    ErrorKind::InvalidResponse("Code size pattern mismatch".to_owned()).into(),
-                );
-            }
-            let code_length = code_length_str[..code_length_str.len() - byte_pattern.len()].parse()?;
-
-            fn parse_result(text: &str) -> Option<SubmissionResult> {
-                use SubmissionResult::*;
-                match text {
-                    "AC" => Some(Pass),
-                    "WA" => Some(Fail),
-                    "TLE" => Some(Timeout),
-                    "RE" => Some(RuntimeError),
-                    "CE" => Some(CompileError),
-                    _ => None
-                }
+) -> Result<(Vec<Submission>, Authentication)> {
+    let endpoint = client.url(&contest.page_path("submissions/me/"));
+    let (auth, body) = get_api(&endpoint, auth, client).await?;
+    let html = str::from_utf8(&body).chain_err(|| {
+        ErrorKind::InvalidResponse("Cannot decode response".to_owned())
+    })?;
+    let page = Page { html, url: Some(&endpoint), dump: client.config.dump_html };
+    Ok((parse_submissions_page(&page)?, auth))
+}
+
+pub fn parse_submissions(html: &str) -> Result<Vec<Submission>> {
+    //! Parse the table of a submissions page. This is split from fetching so
+    //! that saved pages can be checked against layout changes.
+    parse_submissions_page(&Page::new(html))
+}
+
+fn first_text(node: Node<'_>) -> Option<&str> {
+    node.find(Text)
+        .map(|e| e.as_text().unwrap().trim())
+        .find(|s| !s.is_empty())
+}
+
+/// The cells of a table row, reporting failures with their position.
+struct Row<'a> {
+    page: &'a Page<'a>,
+    index: usize,
+    cells: Vec<Node<'a>>,
+}
+
+impl<'a> Row<'a> {
+    fn error(&self, column: usize, expected: &str) -> ScrapeError {
+        ScrapeError::new(format!("td:nth-of-type({})", column + 1), expected).row(self.index)
+    }
+
+    fn cell(&self, column: usize) -> Result<Node<'a>> {
+        self.cells
+            .get(column)
+            .cloned()
+            .ok_or_else(|| self.page.error(self.error(column, "a cell")))
+    }
+
+    fn text(&self, column: usize) -> Result<&'a str> {
+        first_text(self.cell(column)?).ok_or_else(|| self.page.error(self.error(column, "text")))
+    }
+
+    fn parse<T: FromStr>(&self, column: usize, suffix: &str, expected: &str) -> Result<T> {
+        let text = self.text(column)?;
+        if let Some(Ok(value)) = text.strip_suffix(suffix).map(str::parse) {
+            return Ok(value);
+        }
+        Err(self.page.error(self.error(column, expected).text(text)))
+    }
+}
+
+fn parse_submissions_page(page: &Page) -> Result<Vec<Submission>> {
+    let document = Document::from(page.html);
+    let result_tbody = document
+        .find(Name("table").descendant(Name("tbody")))
+        .next()
+        .ok_or_else(|| page.error(ScrapeError::new("table tbody", "a submissions table")))?;
+    result_tbody.children().filter(|e| e.is(Element)).enumerate().map(|(i, row)| {
+        let row = Row {
+            page,
+            index: i + 1,
+            cells: row.children().filter(|e| e.is(Name("td"))).collect(),
+        };
+        let timestamp = row.text(0)?.to_owned();
+        // TODO: chrono parse
+        let task = row.text(1)?.to_owned();
+        // TODO: internal id
+        let user = row.text(2)?.to_owned();
+        let lang = row.text(3)?.to_owned();
+        let score = row.parse(4, "", "a score")?;
+        let code_length = row.parse(5, " Byte", "a code size in bytes")?;
+
+        fn parse_result(text: &str) -> Option<SubmissionResult> {
+            use SubmissionResult::*;
+            match text {
+                "AC" => Some(Pass),
+                "WA" => Some(Fail),
+                "TLE" => Some(Timeout),
+                "RE" => Some(RuntimeError),
+                "CE" => Some(CompileError),
+                _ => None
             }
-            fn parse_status(text: &str) -> Option<SubmissionStatus> {
-                use SubmissionStatus::*;
-                if let Some(x) = parse_result(text) {
-                    Some(Done(x))
-                } else if text == "WJ" {
-                    Some(Pending)
-                } else {
-                    let slash = text.find('/')?;
-                    let current = text[..slash].parse().ok()?;
-                    if let Some(space) = text.find(' ') {
-                        if space < slash {
-                            None
-                        } else {
-                            let total = text[slash + 1..space].parse().ok()?;
-                            let status = parse_result(&text[space + 1..])?;
-                            Some(InProgress { current, total, status })
-                        }
+        }
+        fn parse_status(text: &str) -> Option<SubmissionStatus> {
+            use SubmissionStatus::*;
+            if let Some(x) = parse_result(text) {
+                Some(Done(x))
+            } else if text == "WJ" {
+                Some(Pending)
+            } else {
+                let slash = text.find('/')?;
+                let current = text[..slash].parse().ok()?;
+                if let Some(space) = text.find(' ') {
+                    if space < slash {
+                        None
                     } else {
-                        let total = text[slash + 1..].parse().ok()?;
-                        Some(InProgress { current, total, status: SubmissionResult::Pass })
+                        let total = text[slash + 1..space].parse().ok()?;
+                        let status = parse_result(&text[space + 1..])?;
+                        Some(InProgress { current, total, status })
                     }
+                } else {
+                    let total = text[slash + 1..].parse().ok()?;
+                    Some(InProgress { current, total, status: SubmissionResult::Pass })
                 }
             }
-            let status_node = col_iter.next().ok_or_else(|| {
-                ErrorKind::InvalidResponse("Table layout mismatch".to_owned())
-            })?;
-            let status = parse_status(next_text(&mut status_node.children())?)
-                .ok_or_else(|| ErrorKind::InvalidResponse("Status parse failure".to_owned()))?;
-
-            let (time, memory) =
-                if status_node.attr("colspan") != Some("3") {
-                    let time_str = next_text(&mut col_iter)?;
-                    let ms_pattern = " ms";
-                    if !time_str.ends_with(ms_pattern) {
-                        return Err(
-                            ErrorKind::InvalidResponse("Execution time pattern mismatch".to_owned()).into(),
-                        );
-                    }
-                    let time = time_str[..time_str.len() - ms_pattern.len()].parse()?;
-
-                    let memory_str = next_text(&mut col_iter)?;
-                    let kb_pattern = " KB";
-                    if !memory_str.ends_with(kb_pattern) {
-                        return Err(
-                            ErrorKind::InvalidResponse("Memory usage pattern mismatch".to_owned()).into(),
-                        );
-                    }
-                    let memory = memory_str[..memory_str.len() - kb_pattern.len()].parse()?;
-                    (Some(time), Some(memory))
-                } else { (None, None) };
-
-            let id_href = col_iter.next().ok_or_else(|| ErrorKind::InvalidResponse(
-                "Table layout mismatch".to_owned(),
-            ))?
-                .find(Name("a")).next().ok_or_else(|| ErrorKind::InvalidResponse(
-                "Table layout mismatch".to_owned(),
-            ))?
-                .attr("href").ok_or_else(|| ErrorKind::InvalidResponse(
-                "Table layout mismatch".to_owned(),
-            ))?;
-            let id = id_href[id_href.rfind('/').ok_or_else(|| ErrorKind::InvalidResponse(
-                "Table layout mismatch".to_owned(),
-            ))? + 1..].to_owned();
-
-            Ok(Submission { id, timestamp, task, user, lang, score, code_length, status, time, memory })
-        }).collect::<Result<Vec<Submission>>>()?;
-        Ok((results, auth))
-    })
+        }
+        let status_node = row.cell(6)?;
+        let status_text = status_node
+            .children()
+            .next()
+            .and_then(first_text)
+            .ok_or_else(|| page.error(row.error(6, "a judge status")))?;
+        let status = parse_status(status_text)
+            .ok_or_else(|| page.error(row.error(6, "a judge status").text(status_text)))?;
+
+        // Until judging completes, the status spans the time and memory
+        // columns as well.
+        let (time, memory, detail) =
+            if status_node.attr("colspan") != Some("3") {
+                let time = row.parse(7, " ms", "an execution time in ms")?;
+                let memory = row.parse(8, " KB", "a memory usage in KB")?;
+                (Some(time), Some(memory), 9)
+            } else { (None, None, 7) };
+
+        let id_href = row.cell(detail)?
+            .find(Name("a"))
+            .next()
+            .and_then(|a| a.attr("href"))
+            .ok_or_else(|| page.error(row.error(detail, "a link to the submission")))?;
+        let id = match id_href.rfind('/').map(|slash| id_href[slash + 1..].parse()) {
+            Some(Ok(id)) => id,
+            _ => bail!(page.error(row.error(detail, "a link to the submission").text(id_href))),
+        };
+
+        Ok(Submission { id, timestamp, task, user, lang, score, code_length, status, time, memory })
+    }).collect()
+}
+
+/// Returns a username and password to login with.
+type Credentials = dyn Fn() -> Result<(String, String)> + Send + Sync;
+
+/// A client together with the session cookie it is currently using. The
+/// cookie is replaced after every round trip, so clones of a `Session` always
+/// share the latest one.
+#[derive(Clone)]
+pub struct Session {
+    client: Client,
+    auth: Arc<Mutex<Option<Authentication>>>,
+    credentials: Option<Arc<Credentials>>,
+}
+
+impl Session {
+    pub fn new(client: &Client, auth: Option<Authentication>) -> Session {
+        //! Resume a session, or start an anonymous one if `auth` is `None`.
+        Session {
+            client: client.clone(),
+            auth: Arc::new(Mutex::new(auth)),
+            credentials: None,
+        }
+    }
+
+    pub fn with_credentials<F>(mut self, provider: F) -> Session
+    where
+        F: Fn() -> Result<(String, String)> + Send + Sync + 'static,
+    {
+        //! Set a callback returning a username and password, used to login
+        //! again and retry when a request fails with `SessionExpired`.
+        //! Without one, the error is returned to the caller.
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    pub async fn login(
+        username: &str,
+        password: &str,
+        client: &Client,
+    ) -> Result<(Session, Option<Flash>)> {
+        //! Login with username and password.
+        let (message, auth) = login(username, password, client).await?;
+        Ok((Session::new(client, Some(auth)), message))
+    }
+
+    pub fn authentication(&self) -> Option<Authentication> {
+        //! The current session cookie, e.g. for persisting it.
+        self.auth.lock().unwrap().clone()
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn update(&self, auth: Authentication) {
+        *self.auth.lock().unwrap() = Some(auth);
+    }
+
+    async fn reauthenticate(&self, error: Error) -> Result<Option<Authentication>> {
+        // Login again if the session expired and there is a way to do so.
+        let provider = match self.credentials {
+            Some(ref provider) if matches!(error.kind(), ErrorKind::SessionExpired) => {
+                provider.clone()
+            }
+            _ => return Err(error),
+        };
+        let (username, password) = provider()?;
+        let (_, auth) = login(&username, &password, &self.client).await?;
+        Ok(Some(auth))
+    }
+
+    async fn retrying<T, F, R>(&self, op: F) -> Result<T>
+    where
+        F: Fn(Option<Authentication>) -> R,
+        R: Future<Output = Result<(T, Authentication)>>,
+    {
+        // Run `op` with the current session, and once more after logging in
+        // again if it expired. The session is replaced with the resulting one.
+        let (value, auth) = match op(self.authentication()).await {
+            Err(e) => op(self.reauthenticate(e).await?).await?,
+            Ok(result) => result,
+        };
+        self.update(auth);
+        Ok(value)
+    }
+
+    pub async fn logout(&self) -> Result<Option<Flash>> {
+        //! Logout, replacing the session with an anonymous one.
+        //! # Server-side implementation details
+        //! The server framework, Revel, currently doesn't store sessions in
+        //! database, and thus has no ability to invalidate a token other than
+        //! timing out. Thus, this cannot be used for safety purposes.
+        let (message, auth) = logout(self.authentication(), &self.client).await?;
+        self.update(auth);
+        Ok(message)
+    }
+
+    pub async fn join(&self, contest: &ContestId) -> Result<Option<Flash>> {
+        //! Join a contest.
+        let client = &self.client;
+        self.retrying(|auth| join(contest, auth, client)).await
+    }
+
+    pub async fn resolve_submit(
+        &self,
+        contest: &ContestId,
+        task: &str,
+        lang: &str,
+    ) -> Result<(TaskScreenName, LanguageId)> {
+        //! Find the task and language to `submit` in from the start of their
+        //! names on the submit page, ignoring case, e.g. `a` and `rust`.
+        let client = &self.client;
+        self.retrying(|auth| resolve_submit(contest, task, lang, auth, client)).await
+    }
+
+    pub async fn submit(
+        &self,
+        contest: &ContestId,
+        task: &TaskScreenName,
+        lang: &LanguageId,
+        source: String,
+    ) -> Result<Option<Flash>> {
+        //! Submit a resolution. Fails with `NoSuchTask` or `NoSuchLanguage`
+        //! if the submit page doesn't offer them.
+        let client = &self.client;
+        self.retrying(|auth| submit(contest, task, lang, source.clone(), auth, client)).await
+    }
+
+    pub async fn submissions(&self, contest: &ContestId) -> Result<Vec<Submission>> {
+        //! List own submissions of a contest.
+        let client = &self.client;
+        self.retrying(|auth| submissions(contest, auth, client)).await
+    }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use cookie::Cookie;
+    use reqwest::Url;
+    use super::Authentication;
+
+    #[test]
+    fn test_session_info() {
+        let mut auth = Authentication::default();
+        let cookie = Cookie::parse(
+            "REVEL_SESSION=0a1b2c3d-%00_TS%3A1510000000%00%00UserScreenName%3Achokudai%00\
+             %00a%3Afalse%00; Path=/",
+        ).unwrap();
+        auth.cookies.store(&cookie, &Url::parse("https://atcoder.jp/").unwrap());
+        let info = auth.session_info().unwrap();
+        assert_eq!(info.username, Some("chokudai".to_owned()));
+        assert_eq!(info.expires, Some(1510000000));
+        assert_eq!(info.fields.get("a").map(|s| &**s), Some("false"));
+    }
+}
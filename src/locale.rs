@@ -0,0 +1,106 @@
+//! The language pages are served in, and the messages the server shows
+//! after a form is submitted, which are worded differently in each.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+/// The cookie AtCoder keeps the chosen language in.
+pub const LOCALE_COOKIE: &str = "language";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Locale {
+    English,
+    Japanese,
+}
+
+impl Locale {
+    pub fn code(self) -> &'static str {
+        //! The code used in the `lang` query parameter and the cookie.
+        match self {
+            Locale::English => "en",
+            Locale::Japanese => "ja",
+        }
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Locale {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Locale> {
+        match s {
+            "en" => Ok(Locale::English),
+            "ja" => Ok(Locale::Japanese),
+            _ => bail!("Unknown locale {:?}, expected \"en\" or \"ja\"", s),
+        }
+    }
+}
+
+/// What a flash message says, independently of its language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FlashCode {
+    LoggedIn,
+    LoggedOut,
+    WrongPassword,
+    Registered,
+    AlreadyRegistered,
+    ContestNotStarted,
+    ContestEnded,
+    /// A message not known to this crate. Its text is still available.
+    Other,
+}
+
+/// Fragments of the English and Japanese messages, checked in order.
+///
+/// Entries are only added with the wording taken from responses of the
+/// real site, in both languages. Until then messages are `Other`, and
+/// callers can go by their text.
+const MESSAGES: &[(FlashCode, &[&str])] = &[];
+
+/// A message shown after submitting a form, from the `REVEL_FLASH` cookie.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Flash {
+    pub code: FlashCode,
+    /// The message as the server worded it.
+    pub text: String,
+}
+
+impl Flash {
+    pub fn new(text: String) -> Flash {
+        let code = MESSAGES
+            .iter()
+            .find(|(_, fragments)| fragments.iter().any(|f| text.contains(f)))
+            .map_or(FlashCode::Other, |&(code, _)| code);
+        Flash { code, text }
+    }
+}
+
+impl fmt::Display for Flash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flash, FlashCode};
+
+    fn code(text: &str) -> FlashCode {
+        Flash::new(text.to_owned()).code
+    }
+
+    #[test]
+    fn test_other() {
+        let flash = Flash::new("ようこそ、chokudai さん。".to_owned());
+        assert_eq!(flash.code, FlashCode::Other);
+        assert_eq!(flash.to_string(), "ようこそ、chokudai さん。");
+        assert_eq!(code("The judge has ended maintenance."), FlashCode::Other);
+    }
+}
@@ -1,96 +1,355 @@
 #[macro_use]
 extern crate clap;
-extern crate tokio_core;
-extern crate preferences;
-extern crate rprompt;
-extern crate rpassword;
-extern crate atcoder;
-
-use std::fs::File;
-use std::io::Read;
-use preferences::{AppInfo, Preferences};
+#[macro_use]
+extern crate serde_derive;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::process;
+use std::path::PathBuf;
+use std::time::Duration;
+use log::LevelFilter;
+use preferences::{prefs_base_dir, AppInfo, Preferences, PreferencesError};
 use rprompt::prompt_reply_stderr;
 use rpassword::prompt_password_stderr;
-use tokio_core::reactor::Core;
-use atcoder::{create_client, join, login, logout, submit, submissions, Authentication};
+use atcoder::{Authentication, Config, ErrorKind};
+use atcoder::cache::Cache;
+use atcoder::ids::ContestId;
+use atcoder::store::Store;
+use atcoder::blocking::{Client, Session};
 
 const APP_INFO: AppInfo = AppInfo {
     name: "atcoder",
     author: "Tatsuyuki Ishi",
 };
 
+/// The account used when neither `--account` nor `default_account` is set.
+const DEFAULT_ACCOUNT: &str = "default";
+
+/// Saved sessions by account name.
+type Accounts = BTreeMap<String, Authentication>;
+
+/// Client settings stored under the `config` key, e.g. in
+/// `~/.config/atcoder/config.prefs.json` on Linux. Flags take precedence.
+/// Timeouts are in seconds.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct CliConfig {
+    base_url: Option<String>,
+    proxy: Option<String>,
+    root_certificates: Vec<PathBuf>,
+    connect_timeout: Option<u64>,
+    read_timeout: Option<u64>,
+    user_agent: Option<String>,
+    /// `en` or `ja`.
+    locale: Option<String>,
+    /// Where to cache pages. Caching is disabled when unset.
+    cache_dir: Option<PathBuf>,
+    /// The account used when `--account` isn't given.
+    default_account: Option<String>,
+    /// Encrypt saved sessions with the contents of this file.
+    key_file: Option<PathBuf>,
+    /// Encrypt saved sessions with a passphrase, read from
+    /// `ATCODER_PASSPHRASE` or prompted for.
+    passphrase: bool,
+}
+
+fn load<T: Preferences + Default>(key: &str) -> T {
+    //! Load a preferences key, which doesn't exist until first saved.
+    match T::load(&APP_INFO, key) {
+        Err(PreferencesError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => T::default(),
+        result => result.unwrap(),
+    }
+}
+
+fn app_dir() -> PathBuf {
+    prefs_base_dir().expect("No configuration directory").join(APP_INFO.name)
+}
+
+fn account_store(cli_config: &CliConfig) -> Store {
+    let path = app_dir().join("accounts.json");
+    if let Some(ref key_file) = cli_config.key_file {
+        match Store::key_file(path, key_file) {
+            Ok(store) => store,
+            Err(e) => {
+                eprintln!("Failed to read the key file {}: {}", key_file.display(), e);
+                process::exit(1);
+            }
+        }
+    } else if cli_config.passphrase {
+        let passphrase = match env::var("ATCODER_PASSPHRASE") {
+            Ok(passphrase) => passphrase,
+            Err(_) => prompt_password_stderr("Passphrase: ").unwrap(),
+        };
+        Store::encrypted(path, passphrase.as_bytes())
+    } else {
+        Store::plain(path)
+    }
+}
+
+fn load_accounts(store: &Store) -> Accounts {
+    //! Sessions saved by older versions in the preferences, under the
+    //! `accounts` or the single `auth` key, are moved to the store when it
+    //! is next saved.
+    match store.load() {
+        Ok(Some(accounts)) => return accounts,
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", store.path().display(), e);
+            if let ErrorKind::StoreEncrypted = *e.kind() {
+                eprintln!("Pass --passphrase or --key-file, or set them in the config.");
+            }
+            process::exit(1);
+        }
+    }
+    let mut accounts: Accounts = load("accounts");
+    if accounts.is_empty() {
+        if let Ok(auth) = Authentication::load(&APP_INFO, "auth") {
+            accounts.insert(DEFAULT_ACCOUNT.to_owned(), auth);
+        }
+    }
+    accounts
+}
+
+fn save_accounts(store: &Store, accounts: &Accounts) {
+    //! Save the sessions, then delete the preferences they may have been
+    //! migrated from, which would otherwise keep them in plain text.
+    store.save(accounts).unwrap();
+    for key in &["accounts", "auth"] {
+        let path = app_dir().join(format!("{}.prefs.json", key));
+        match fs::remove_file(&path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Warning: failed to delete {}: {}", path.display(), e),
+            Ok(()) => {}
+        }
+    }
+}
+
 fn main() {
     let matches = clap_app! (
         @app (app_from_crate!())
+        (@arg verbose: -v --verbose +multiple +global
+            "Log HTTP requests, repeat to log more details")
+        (@arg base_url: --("base-url") +takes_value +global "Origin to send requests to")
+        (@arg proxy: --proxy +takes_value +global "Proxy to send requests through")
+        (@arg root_certificate: --("root-certificate") +takes_value +multiple number_of_values(1)
+            +global "PEM file with an extra root certificate to trust")
+        (@arg connect_timeout: --("connect-timeout") +takes_value +global
+            "Seconds to wait for a connection")
+        (@arg read_timeout: --("read-timeout") +takes_value +global
+            "Seconds to wait for the server to send data")
+        (@arg user_agent: --("user-agent") +takes_value +global "User-Agent to send")
+        (@arg locale: --locale +takes_value +global possible_values(&["en", "ja"])
+            "Language to show pages and messages in")
+        (@arg cache_dir: --("cache-dir") +takes_value +global "Directory to cache pages in")
+        (@arg refresh: --refresh +global "Fetch pages again even if they are cached")
+        (@arg account: --account +takes_value +global "Name of the saved account to use")
+        (@arg key_file: --("key-file") +takes_value +global
+            "Encrypt saved sessions with the contents of this file")
+        (@arg passphrase: --passphrase +global "Encrypt saved sessions with a passphrase")
         (@subcommand login => )
         (@subcommand logout => )
+        (@subcommand whoami => )
         (@subcommand join => (@arg contest: +required))
         (@subcommand submit => (@arg contest: +required)
                                (@arg task: +required)
                                (@arg lang: +required)
                                (@arg file: +required))
         (@subcommand status => (@arg contest: +required))
+        (@subcommand accounts =>
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand list => )
+            (@subcommand remove => (@arg name: +required))
+            (@subcommand default => (@arg name: +required)))
     ).get_matches();
 
-    let mut core = Core::new().unwrap();
-    let client = create_client(&core.handle()).unwrap();
+    let level = match matches.occurrences_of("verbose") {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    // `RUST_LOG` can still override this for specific modules.
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Warn)
+        .filter_module("atcoder", level)
+        .parse_default_env()
+        .init();
 
-    if let Some(_matches) = matches.subcommand_matches("login") {
+    let mut cli_config: CliConfig = load("config");
+    let account = matches
+        .value_of("account")
+        .map(str::to_owned)
+        .or_else(|| cli_config.default_account.clone())
+        .unwrap_or_else(|| DEFAULT_ACCOUNT.to_owned());
+
+    if let Some(base_url) = matches.value_of("base_url") {
+        cli_config.base_url = Some(base_url.to_owned());
+    }
+    if let Some(proxy) = matches.value_of("proxy") {
+        cli_config.proxy = Some(proxy.to_owned());
+    }
+    if let Some(paths) = matches.values_of("root_certificate") {
+        cli_config.root_certificates.extend(paths.map(PathBuf::from));
+    }
+    if matches.is_present("connect_timeout") {
+        cli_config.connect_timeout = Some(value_t_or_exit!(matches, "connect_timeout", u64));
+    }
+    if matches.is_present("read_timeout") {
+        cli_config.read_timeout = Some(value_t_or_exit!(matches, "read_timeout", u64));
+    }
+    if let Some(user_agent) = matches.value_of("user_agent") {
+        cli_config.user_agent = Some(user_agent.to_owned());
+    }
+    if let Some(locale) = matches.value_of("locale") {
+        cli_config.locale = Some(locale.to_owned());
+    }
+    if let Some(cache_dir) = matches.value_of("cache_dir") {
+        cli_config.cache_dir = Some(PathBuf::from(cache_dir));
+    }
+    if let Some(key_file) = matches.value_of("key_file") {
+        cli_config.key_file = Some(PathBuf::from(key_file));
+    }
+    if matches.is_present("passphrase") {
+        cli_config.passphrase = true;
+    }
+
+    let store = account_store(&cli_config);
+
+    if let Some(matches) = matches.subcommand_matches("accounts") {
+        let mut accounts = load_accounts(&store);
+        if matches.subcommand_matches("list").is_some() {
+            let default = cli_config.default_account.as_deref().unwrap_or(DEFAULT_ACCOUNT);
+            for name in accounts.keys() {
+                let marker = if name == default { "*" } else { " " };
+                println!("{} {}", marker, name);
+            }
+        } else if let Some(matches) = matches.subcommand_matches("remove") {
+            let name = matches.value_of("name").unwrap();
+            if accounts.remove(name).is_none() {
+                eprintln!("No account named {}", name);
+                process::exit(1);
+            }
+            save_accounts(&store, &accounts);
+        } else if let Some(matches) = matches.subcommand_matches("default") {
+            // Only change the file, not the settings overridden by flags.
+            let mut file_config: CliConfig = load("config");
+            file_config.default_account = Some(matches.value_of("name").unwrap().to_owned());
+            file_config.save(&APP_INFO, "config").unwrap();
+        }
+        return;
+    }
+
+    let mut config = Config::default();
+    if let Some(base_url) = cli_config.base_url {
+        config.base_url = base_url;
+    }
+    config.proxy = cli_config.proxy;
+    config.root_certificates = cli_config.root_certificates;
+    if let Some(seconds) = cli_config.connect_timeout {
+        config.connect_timeout = Some(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = cli_config.read_timeout {
+        config.read_timeout = Some(Duration::from_secs(seconds));
+    }
+    if let Some(user_agent) = cli_config.user_agent {
+        config.user_agent = user_agent;
+    }
+    config.locale = cli_config.locale.map(|locale| locale.parse().unwrap());
+    config.cache = cli_config.cache_dir.map(|dir| Cache {
+        dir,
+        refresh: matches.is_present("refresh"),
+    });
+    let client = Client::new(config).unwrap();
+
+    let session = if let Some(_matches) = matches.subcommand_matches("login") {
         // TODO: get credentials as parameter
         let username = prompt_reply_stderr("Username: ").unwrap();
         let password = prompt_password_stderr("Password: ").unwrap();
-        let (auth, message) = core.run(login(&username, &password, &client)).unwrap();
-        auth.save(&APP_INFO, "auth").unwrap();
+        let (session, message) = Session::login(&username, &password, &client).unwrap();
         if let Some(message) = message {
             println!("Login successful: {}", message)
         } else {
             println!("Login successful");
         };
+        session
     } else {
-        let auth = Authentication::load(&APP_INFO, "auth").unwrap();
-        if let Some(_) = matches.subcommand_matches("logout") {
-            let message = core.run(logout(auth, &client)).unwrap();
+        let auth = match load_accounts(&store).remove(&account) {
+            Some(auth) => auth,
+            None => {
+                eprintln!("No session saved for account {}, login first", account);
+                process::exit(1);
+            }
+        };
+        let session = Session::new(&client, Some(auth)).with_credentials(|| {
+            eprintln!("Session expired, please login again.");
+            let username = prompt_reply_stderr("Username: ").map_err(|e| e.to_string())?;
+            let password = prompt_password_stderr("Password: ").map_err(|e| e.to_string())?;
+            Ok((username, password))
+        });
+        if matches.subcommand_matches("whoami").is_some() {
+            let info = session.authentication().unwrap().session_info().unwrap();
+            if let Some(username) = info.username {
+                println!("Logged in as {}", username);
+            } else {
+                println!("Not logged in");
+            }
+            if let Some(expires) = info.expires {
+                println!("Session expires at {}", time::at(time::Timespec::new(expires, 0)).rfc822());
+            }
+        } else if matches.subcommand_matches("logout").is_some() {
+            let message = session.logout().unwrap();
             if let Some(message) = message {
                 println!("Logout successful: {}", message)
             } else {
                 println!("Logout successful");
             };
+            // The session is anonymous now, so forget the account instead.
+            let mut accounts = load_accounts(&store);
+            accounts.remove(&account);
+            save_accounts(&store, &accounts);
+            return;
         } else if let Some(matches) = matches.subcommand_matches("join") {
-            let (message, auth) = core.run(
-                join(matches.value_of("contest").unwrap(), auth, &client),
-            ).unwrap();
+            let contest = value_t_or_exit!(matches, "contest", ContestId);
+            let message = session.join(&contest).unwrap();
             if let Some(message) = message {
                 println!("Join successful: {}", message)
             } else {
                 println!("Join successful");
             };
-            auth.save(&APP_INFO, "auth").unwrap();
         } else if let Some(matches) = matches.subcommand_matches("submit") {
             let mut file = File::open(matches.value_of("file").unwrap()).unwrap();
             let mut contents = String::new();
             file.read_to_string(&mut contents).unwrap();
-            let (message, auth) = core.run(submit(
-                matches.value_of("contest").unwrap(),
-                matches.value_of("task").unwrap(),
-                matches.value_of("lang").unwrap(),
-                contents,
-                auth,
-                &client,
-            )).unwrap();
+            let contest = value_t_or_exit!(matches, "contest", ContestId);
+            let (task, lang) = session
+                .resolve_submit(
+                    &contest,
+                    matches.value_of("task").unwrap(),
+                    matches.value_of("lang").unwrap(),
+                )
+                .unwrap();
+            let message = session.submit(&contest, &task, &lang, contents).unwrap();
             if let Some(message) = message {
                 println!("Submit successful: {}", message)
             } else {
                 println!("Submit successful");
             };
-            auth.save(&APP_INFO, "auth").unwrap();
         } else if let Some(matches) = matches.subcommand_matches("status") {
-            let (submissions, auth) = core.run(
-                submissions(matches.value_of("contest").unwrap(), Some(auth), &client),
-            ).unwrap();
+            let contest = value_t_or_exit!(matches, "contest", ContestId);
+            let submissions = session.submissions(&contest).unwrap();
             for submission in submissions {
                 println!("{} {} {} {}", submission.timestamp, submission.task, submission.lang, submission.status);
             }
-            auth.save(&APP_INFO, "auth").unwrap();
         }
+        session
+    };
+    if let Some(auth) = session.authentication() {
+        // Reload, as other accounts may have been used in the meantime.
+        let mut accounts = load_accounts(&store);
+        accounts.insert(account, auth);
+        save_accounts(&store, &accounts);
     }
 }
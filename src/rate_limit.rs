@@ -0,0 +1,154 @@
+//! A token bucket limiting how fast a client sends requests, so that
+//! scripts running several commands at once stay polite to the server.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::transport::{Request, ResponseFuture, Transport};
+
+/// Allows bursts of up to `burst` requests, refilled at one every `interval`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            burst: 5,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn from_seconds(seconds: f64) -> Duration {
+    Duration::new(seconds as u64, (seconds.fract() * 1e9) as u32)
+}
+
+struct Bucket {
+    /// Goes below zero when requests are waiting for a token.
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The state of a `RateLimit`, shared by every clone of a `Client`.
+pub struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> RateLimiter {
+        let tokens = limit.burst as f64;
+        RateLimiter {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    pub fn wait(&self) -> Duration {
+        //! How long a request made now would be delayed.
+        self.wait_at(Instant::now())
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        if now > bucket.updated {
+            let refilled = seconds(now - bucket.updated) / seconds(self.limit.interval);
+            bucket.tokens = (bucket.tokens + refilled).min(self.limit.burst as f64);
+            bucket.updated = now;
+        }
+    }
+
+    fn wait_at(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, now);
+        self.wait_for(&bucket)
+    }
+
+    fn wait_for(&self, bucket: &Bucket) -> Duration {
+        let missing = 1.0 - bucket.tokens;
+        if missing > 0.0 {
+            from_seconds(missing * seconds(self.limit.interval))
+        } else {
+            Duration::new(0, 0)
+        }
+    }
+
+    fn acquire_at(&self, now: Instant) -> Duration {
+        //! Take a token, returning how long to wait until it is actually
+        //! available. Requests are thus let through in the order they asked.
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, now);
+        let wait = self.wait_for(&bucket);
+        bucket.tokens -= 1.0;
+        wait
+    }
+}
+
+/// Passes requests on to another transport, delaying them to stay within
+/// the limit of a `RateLimiter`.
+pub struct RateLimitTransport {
+    inner: Arc<dyn Transport>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitTransport {
+    pub fn new(inner: Arc<dyn Transport>, limiter: Arc<RateLimiter>) -> RateLimitTransport {
+        RateLimitTransport { inner, limiter }
+    }
+}
+
+impl Transport for RateLimitTransport {
+    fn send(&self, request: Request) -> ResponseFuture {
+        let wait = self.limiter.acquire_at(Instant::now());
+        if wait == Duration::new(0, 0) {
+            return self.inner.send(request);
+        }
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            tokio::time::sleep(wait).await;
+            inner.send(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{RateLimit, RateLimiter};
+
+    #[test]
+    fn test_bucket() {
+        let limiter = RateLimiter::new(RateLimit {
+            burst: 2,
+            interval: Duration::from_millis(100),
+        });
+        let start = limiter.bucket.lock().unwrap().updated;
+        assert_eq!(limiter.acquire_at(start), Duration::new(0, 0));
+        assert_eq!(limiter.acquire_at(start), Duration::new(0, 0));
+        assert_eq!(limiter.wait_at(start), Duration::from_millis(100));
+        // Waiting requests queue up behind each other.
+        assert_eq!(limiter.acquire_at(start), Duration::from_millis(100));
+        assert_eq!(limiter.wait_at(start), Duration::from_millis(200));
+
+        let later = start + Duration::from_millis(250);
+        assert_eq!(limiter.wait_at(later), Duration::new(0, 0));
+        // The bucket doesn't fill beyond the burst size.
+        let idle = start + Duration::from_secs(60);
+        assert_eq!(limiter.acquire_at(idle), Duration::new(0, 0));
+        assert_eq!(limiter.acquire_at(idle), Duration::new(0, 0));
+        assert_eq!(limiter.wait_at(idle), Duration::from_millis(100));
+    }
+}
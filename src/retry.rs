@@ -0,0 +1,178 @@
+//! Retrying requests that fail for transient reasons, like the gateway
+//! errors AtCoder returns under load at the start of a contest.
+
+use std::error::Error as StdError;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use rand_core::{OsRng, RngCore};
+use reqwest::StatusCode;
+
+use crate::{Error, ErrorKind, Result};
+use crate::transport::{Method, Request, Response, ResponseFuture, Transport};
+
+/// When and how often to retry a request.
+///
+/// GET requests are idempotent and are retried on any of `statuses` or
+/// `io_errors`. Other requests may have had an effect even when the response
+/// was an error, so they are only retried on `connect_errors`, which show
+/// that the request never reached the server.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Attempts per request, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled for each one after it.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// The fraction of each delay that is randomized, from 0 to 1, so that
+    /// clients that failed together don't retry together.
+    pub jitter: f64,
+    pub statuses: Vec<StatusCode>,
+    pub io_errors: Vec<io::ErrorKind>,
+    /// Failures to connect reported by reqwest, including connect timeouts,
+    /// count as `NotConnected`.
+    pub connect_errors: Vec<io::ErrorKind>,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy { max_attempts: 1, ..RetryPolicy::default() }
+    }
+
+    fn retryable(&self, method: Method, result: &Result<Response>) -> bool {
+        match result {
+            Ok(response) => method == Method::Get && self.statuses.contains(&response.status),
+            Err(e) => match io_error_kind(e) {
+                Some(kind) => {
+                    self.connect_errors.contains(&kind) ||
+                        (method == Method::Get && self.io_errors.contains(&kind))
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        //! The delay after the `attempt`th attempt, counted from 1.
+        let base = self.initial_backoff
+            .checked_mul(1 << (attempt - 1).min(16))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff));
+        let jitter = self.jitter.clamp(0.0, 1.0) * random();
+        let nanos = (base.as_secs() as f64 * 1e9 + base.subsec_nanos() as f64) * (1.0 - jitter);
+        Duration::new((nanos / 1e9) as u64, (nanos % 1e9) as u32)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            jitter: 0.5,
+            statuses: vec![
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            io_errors: vec![
+                io::ErrorKind::TimedOut,
+                io::ErrorKind::ConnectionReset,
+                io::ErrorKind::ConnectionAborted,
+                io::ErrorKind::BrokenPipe,
+                io::ErrorKind::UnexpectedEof,
+            ],
+            connect_errors: vec![
+                io::ErrorKind::NotConnected,
+                io::ErrorKind::ConnectionRefused,
+                io::ErrorKind::AddrNotAvailable,
+            ],
+        }
+    }
+}
+
+/// A number in `[0, 1)`. The clock won't do, as it is about the same for
+/// processes that failed together.
+fn random() -> f64 {
+    (OsRng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn io_error_kind(error: &Error) -> Option<io::ErrorKind> {
+    match error.kind() {
+        ErrorKind::IoError(e) => Some(e.kind()),
+        // Checked first, as a connect timeout is a timeout too. Either way,
+        // the request never reached the server.
+        ErrorKind::ReqError(e) if e.is_connect() => Some(io::ErrorKind::NotConnected),
+        ErrorKind::ReqError(e) if e.is_timeout() => Some(io::ErrorKind::TimedOut),
+        ErrorKind::ReqError(e) => {
+            let mut source = e.source();
+            while let Some(cause) = source {
+                if let Some(e) = cause.downcast_ref::<io::Error>() {
+                    return Some(e.kind());
+                }
+                source = cause.source();
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Passes requests on to another transport, retrying them according to a
+/// `RetryPolicy`. When every attempt fails, the last response or error is
+/// returned.
+pub struct RetryTransport {
+    inner: Arc<dyn Transport>,
+    policy: RetryPolicy,
+}
+
+impl RetryTransport {
+    pub fn new(inner: Arc<dyn Transport>, policy: RetryPolicy) -> RetryTransport {
+        RetryTransport { inner, policy }
+    }
+}
+
+impl Transport for RetryTransport {
+    fn send(&self, request: Request) -> ResponseFuture {
+        let inner = self.inner.clone();
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let mut attempt = 1;
+            loop {
+                let result = inner.send(request.clone()).await;
+                if attempt >= policy.max_attempts || !policy.retryable(request.method, &result) {
+                    return result;
+                }
+                let backoff = policy.backoff(attempt);
+                info!("Retrying {} {} in {:?}", request.method, request.url, backoff);
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::RetryPolicy;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(5), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(40), Duration::from_millis(1000));
+
+        let policy = RetryPolicy { jitter: 0.5, ..policy };
+        let delay = policy.backoff(2);
+        assert!(delay > Duration::from_millis(100) && delay <= Duration::from_millis(200));
+    }
+}
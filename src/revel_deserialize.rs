@@ -1,15 +1,27 @@
-use serde::de;
-use std::fmt::Display;
+use serde::de::{self, IntoDeserializer};
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::str::{self, FromStr};
 use percent_encoding::percent_decode;
 
 error_chain! {
     errors {
         Message(msg: String)
-        InvalidInput {
+        InvalidInput(at: Position) {
             description("Invalid input")
+            display("Invalid input at {}", at)
         }
-        Eof {
+        Eof(at: Position) {
             description("Unexpected end-of-stream")
+            display("Unexpected end-of-stream at {}", at)
+        }
+        Unsupported(what: &'static str) {
+            description("Unsupported data type")
+            display("Unsupported data type: {}", what)
+        }
+        ReservedCharacter(s: String) {
+            description("String contains a reserved character")
+            display("String contains a reserved character: {:?}", s)
         }
     }
 }
@@ -23,239 +35,318 @@ impl de::Error for Error {
     }
 }
 
+/// Where in a cookie decoding failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    /// Byte offset into the raw, still encoded, input.
+    pub offset: usize,
+    /// The key of the entry being decoded, if it was read already.
+    pub key: Option<String>,
+    /// The raw input surrounding `offset`.
+    pub snippet: String,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "byte {}", self.offset)?;
+        if let Some(ref key) = self.key {
+            write!(f, " (key {:?})", key)?;
+        }
+        write!(f, " near {:?}", self.snippet)
+    }
+}
+
+/// Bytes of context on each side of the error in `Position::snippet`.
+const SNIPPET_RADIUS: usize = 16;
+
 pub struct Deserializer<'de> {
-    input: ::std::iter::Peekable<
-        ::std::iter::Map<::percent_encoding::PercentDecode<'de>, fn(u8) -> u8>,
-    >,
+    input: &'de [u8],
+    pos: usize,
+    /// Range of the raw key of the current entry, used in error reports.
+    key: Option<(usize, usize)>,
+    /// Whether a value is being read. Only keys end at a colon, values may
+    /// contain them.
+    in_value: bool,
+}
+
+/// The separator at the start of `input` and its encoded length. Revel
+/// escapes them, but raw ones are accepted too.
+fn separator(input: &[u8]) -> Option<(u8, usize)> {
+    if input.starts_with(b"%00") {
+        Some((0, 3))
+    } else if input.starts_with(b"%3A") || input.starts_with(b"%3a") {
+        Some((b':', 3))
+    } else {
+        match input.first() {
+            Some(&0) => Some((0, 1)),
+            Some(&b':') => Some((b':', 1)),
+            _ => None,
+        }
+    }
+}
+
+/// Undo the percent-encoding, borrowing when there is nothing to decode.
+fn decode(raw: &[u8]) -> Cow<'_, [u8]> {
+    if !raw.iter().any(|&x| x == b'%' || x == b'+') {
+        return Cow::Borrowed(raw);
+    }
+    let unplussed: Vec<u8> = raw.iter()
+        .map(|&x| if x == b'+' { b' ' } else { x })
+        .collect();
+    Cow::Owned(percent_decode(&unplussed).collect())
 }
 
 impl<'de> Deserializer<'de> {
     /// Create a revel deserializer.
     pub fn from_bytes(input: &'de [u8]) -> Self {
-        fn transform(x: u8) -> u8 {
-            if x == b'+' {
-                b' '
-            } else {
-                x
-            }
-        }
         Deserializer {
-            input: percent_decode(input)
-                .map(transform as fn(u8) -> u8)
-                .peekable(),
+            input,
+            pos: 0,
+            key: None,
+            in_value: false,
         }
     }
 
-    fn next_byte(&mut self) -> Result<u8> {
-        self.input.next().ok_or(ErrorKind::Eof.into())
-    }
-
-    fn parse_sequence(&mut self) -> Result<Vec<u8>> {
-        match self.input.clone().position(|x| x == b':' || x == 0) {
-            Some(len) => Ok((&mut self.input).take(len).collect()),
-            None => {
-                bail!(ErrorKind::Eof);
-            }
+    fn position(&self, offset: usize) -> Position {
+        let key = self.key
+            .map(|(start, end)| String::from_utf8_lossy(&decode(&self.input[start..end])).into_owned());
+        let start = offset.saturating_sub(SNIPPET_RADIUS);
+        let end = ::std::cmp::min(offset + SNIPPET_RADIUS, self.input.len());
+        Position {
+            offset,
+            key,
+            snippet: String::from_utf8_lossy(&self.input[start..end]).into_owned(),
         }
     }
-}
-
-impl<'a, 'de> de::Deserializer<'de> for &'a mut Deserializer<'de> {
-    type Error = Error;
 
-    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        visitor.visit_string(String::from_utf8(self.parse_sequence()?)
-            .chain_err(|| ErrorKind::InvalidInput)?)
+    fn invalid(&self, offset: usize) -> ErrorKind {
+        ErrorKind::InvalidInput(self.position(offset))
     }
 
-    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        self.deserialize_string(visitor)
+    /// Report errors raised by a visitor, e.g. for a value of the wrong type,
+    /// at the value they were raised for.
+    fn visited<T>(&self, start: usize, result: Result<T>) -> Result<T> {
+        result.map_err(|e| match *e.kind() {
+            ErrorKind::Message(_) => Error::with_chain(e, self.invalid(start)),
+            _ => e,
+        })
     }
 
-    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        visitor.visit_some(self)
+    fn eof(&self) -> ErrorKind {
+        ErrorKind::Eof(self.position(self.input.len()))
     }
 
-    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        self.deserialize_string(visitor)
+    fn is_empty(&self) -> bool {
+        self.pos >= self.input.len()
     }
 
-    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        visitor.visit_map(SequenceParser { de: self })
+    fn expect_separator(&mut self, expected: u8) -> Result<()> {
+        ensure!(!self.is_empty(), self.eof());
+        match separator(&self.input[self.pos..]) {
+            Some((found, len)) if found == expected => {
+                self.pos += len;
+                Ok(())
+            }
+            _ => bail!(self.invalid(self.pos)),
+        }
     }
 
-    fn deserialize_struct<V>(
-        self,
-        _name: &'static str,
-        _fields: &'static [&'static str],
-        visitor: V,
-    ) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        self.deserialize_map(visitor)
+    /// Consume the still-encoded bytes up to the next separator.
+    fn parse_raw(&mut self) -> Result<&'de [u8]> {
+        let start = self.pos;
+        let mut end = start;
+        while end < self.input.len() {
+            match separator(&self.input[end..]) {
+                Some((b':', _)) if self.in_value => {}
+                Some(_) => {
+                    self.pos = end;
+                    return Ok(&self.input[start..end]);
+                }
+                None => {}
+            }
+            end += 1;
+        }
+        bail!(self.eof());
     }
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        unimplemented!()
+    /// Decode the next sequence, borrowing from the input when it contains no
+    /// escapes.
+    fn parse_sequence(&mut self) -> Result<Cow<'de, [u8]>> {
+        Ok(decode(self.parse_raw()?))
     }
 
-    fn deserialize_bool<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        unimplemented!()
+    fn parse_str(&mut self) -> Result<Cow<'de, str>> {
+        let start = self.pos;
+        Ok(match self.parse_sequence()? {
+            Cow::Borrowed(bytes) => {
+                Cow::Borrowed(str::from_utf8(bytes).chain_err(|| self.invalid(start))?)
+            }
+            Cow::Owned(bytes) => {
+                Cow::Owned(String::from_utf8(bytes).chain_err(|| self.invalid(start))?)
+            }
+        })
     }
 
-    fn deserialize_i8<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        unimplemented!()
+    fn parse_from_str<T: FromStr>(&mut self) -> Result<T> {
+        let start = self.pos;
+        let s = self.parse_str()?;
+        s.parse().map_err(|_| self.invalid(start).into())
     }
+}
 
-    fn deserialize_i16<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        unimplemented!()
+/// Implement `deserialize_*` methods that parse the value with `FromStr`.
+macro_rules! deserialize_from_str {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de>,
+            {
+                visitor.$visit(self.parse_from_str()?)
+            }
+        )*
     }
+}
 
-    fn deserialize_i32<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        unimplemented!()
-    }
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
 
-    fn deserialize_i64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        let start = self.pos;
+        let result = match self.parse_str()? {
+            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Cow::Owned(s) => visitor.visit_string(s),
+        };
+        self.visited(start, result)
     }
 
-    fn deserialize_u8<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_str(visitor)
     }
 
-    fn deserialize_u16<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_str(visitor)
     }
 
-    fn deserialize_u32<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_some(self)
     }
 
-    fn deserialize_u64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        // Skip without decoding, so that unknown fields can't cause errors.
+        self.parse_raw()?;
+        visitor.visit_unit()
     }
 
-    fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_map(SequenceParser { de: self })
     }
 
-    fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_map(visitor)
     }
 
-    fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        // The format is untyped, so everything is a string.
+        self.deserialize_str(visitor)
     }
 
-    fn deserialize_str<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        unimplemented!()
+    deserialize_from_str! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        match self.parse_sequence()? {
+            Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+        }
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_bytes(visitor)
     }
 
-    fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        let start = self.pos;
+        ensure!(self.parse_raw()?.is_empty(), self.invalid(start));
+        visitor.visit_unit()
     }
 
-    fn deserialize_unit_struct<V>(self, _name: &'static str, _visitor: V) -> Result<V::Value>
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, _visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_seq<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        bail!(ErrorKind::Unsupported("sequences"))
     }
 
     fn deserialize_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        bail!(ErrorKind::Unsupported("tuples"))
     }
 
     fn deserialize_tuple_struct<V>(
@@ -267,19 +358,24 @@ impl<'a, 'de> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        bail!(ErrorKind::Unsupported("tuple structs"))
     }
 
     fn deserialize_enum<V>(
         self,
         _name: &'static str,
-        _variants: &'static [&'static str],
-        _visitor: V,
+        variants: &'static [&'static str],
+        visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!()
+        // Only unit variants can be represented, by their name.
+        let start = self.pos;
+        let variant = self.parse_str()?;
+        ensure!(variants.contains(&&*variant), self.invalid(start));
+        let result = visitor.visit_enum(variant.into_owned().into_deserializer());
+        self.visited(start, result)
     }
 }
 
@@ -294,11 +390,15 @@ impl<'de, 'a> de::MapAccess<'de> for SequenceParser<'a, 'de> {
     where
         K: de::DeserializeSeed<'de>,
     {
-        if self.de.input.peek().is_none() {
+        self.de.key = None;
+        if self.de.is_empty() {
             Ok(None)
         } else {
-            ensure!(self.de.next_byte()? == 0, ErrorKind::InvalidInput);
-            seed.deserialize(&mut *self.de).map(Some)
+            self.de.expect_separator(0)?;
+            let start = self.de.pos;
+            let key = seed.deserialize(&mut *self.de)?;
+            self.de.key = Some((start, self.de.pos));
+            Ok(Some(key))
         }
     }
 
@@ -306,10 +406,13 @@ impl<'de, 'a> de::MapAccess<'de> for SequenceParser<'a, 'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        ensure!(self.de.next_byte()? == b':', ErrorKind::InvalidInput);
+        self.de.expect_separator(b':')?;
+        self.de.in_value = true;
         let result = seed.deserialize(&mut *self.de);
-        ensure!(self.de.next_byte()? == 0, ErrorKind::InvalidInput);
-        result
+        self.de.in_value = false;
+        let result = result?;
+        self.de.expect_separator(0)?;
+        Ok(result)
     }
 }
 
@@ -321,7 +424,7 @@ where
     de::Deserialize::deserialize(&mut de)
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct RevelFlash {
     pub success: Option<String>,
     pub error: Option<String>,
@@ -337,7 +440,19 @@ mod tests {
             .iter()
             .map(|&(x, y)| (x.to_owned(), y.to_owned()))
             .collect();
-        let actual = super::from_bytes(&*input).unwrap();
+        let actual = super::from_bytes(input).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_colon_in_value() {
+        use std::collections::BTreeMap;
+        let input = b"%00error%3AError%3A+x%00%00at%3A21:00%00";
+        let expected: BTreeMap<_, _> = [("error", "Error: x"), ("at", "21:00")]
+            .iter()
+            .map(|&(x, y)| (x.to_owned(), y.to_owned()))
+            .collect();
+        let actual: BTreeMap<String, String> = super::from_bytes(input).unwrap();
         assert_eq!(expected, actual);
     }
 
@@ -348,7 +463,134 @@ mod tests {
             success: None,
             error: Some("Wrong password".to_owned()),
         };
-        let actual = super::from_bytes(&*input).unwrap();
+        let actual = super::from_bytes(input).unwrap();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_borrow() {
+        use std::borrow::Cow;
+        use std::collections::HashMap;
+
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            #[serde(borrow)]
+            plain: Cow<'a, str>,
+            #[serde(borrow)]
+            escaped: Cow<'a, str>,
+            name: &'a str,
+        }
+
+        let input = b"%00plain%3Aabc%00%00escaped%3Aa+b%2Bc%00%00name%3Achokudai%00";
+        let actual: Borrowed = super::from_bytes(input).unwrap();
+        match actual.plain {
+            Cow::Borrowed("abc") => {}
+            other => panic!("expected a borrow, got {:?}", other),
+        }
+        assert_eq!(actual.escaped, "a b+c");
+        assert_eq!(actual.name, "chokudai");
+
+        let input = b"%00a%3Ab%00";
+        let actual: HashMap<&str, &str> = super::from_bytes(input).unwrap();
+        assert_eq!(actual.get("a"), Some(&"b"));
+    }
+
+    #[test]
+    fn test_error_position() {
+        use std::collections::HashMap;
+        use super::{ErrorKind, Position, Result};
+
+        let input = b"%00a%3A1%00%00count%3Aabc%00";
+        let result: Result<HashMap<String, u32>> = super::from_bytes(input);
+        match *result.unwrap_err().kind() {
+            ErrorKind::InvalidInput(ref at) => assert_eq!(
+                *at,
+                Position {
+                    offset: 22,
+                    key: Some("count".to_owned()),
+                    snippet: "A1%00%00count%3Aabc%00".to_owned(),
+                }
+            ),
+            ref other => panic!("unexpected error: {}", other),
+        }
+
+        let input = b"%00a%3Ab";
+        let result: Result<HashMap<String, String>> = super::from_bytes(input);
+        match *result.unwrap_err().kind() {
+            ErrorKind::Eof(ref at) => {
+                assert_eq!(at.offset, 8);
+                assert_eq!(at.key, Some("a".to_owned()));
+            }
+            ref other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Kind {
+        Normal,
+        Unrated,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Typed {
+        #[serde(rename = "_TS")]
+        ts: i64,
+        a: bool,
+        kind: Kind,
+        empty: (),
+        ratio: f64,
+    }
+
+    #[test]
+    fn test_typed() {
+        let input = b"%00_TS%3A1510000000%00%00a%3Atrue%00%00kind%3AUnrated%00%00empty%3A%00\
+                      %00ratio%3A0.5%00%00unknown%3A%FF%FE%00";
+        let expected = Typed {
+            ts: 1510000000,
+            a: true,
+            kind: Kind::Unrated,
+            empty: (),
+            ratio: 0.5,
+        };
+        let actual = super::from_bytes(input).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_malformed() {
+        use super::{ErrorKind, Result};
+        let inputs: &[&[u8]] = &[
+            b"%00_TS%3Anot-a-number%00%00a%3Atrue%00%00kind%3ANormal%00%00empty%3A%00%00ratio%3A1%00",
+            b"%00_TS%3A1%00%00a%3Ayes%00%00kind%3ANormal%00%00empty%3A%00%00ratio%3A1%00",
+            b"%00_TS%3A1%00%00a%3Atrue%00%00kind%3AOther%00%00empty%3A%00%00ratio%3A1%00",
+            b"%00_TS%3A1%00%00a%3Atrue%00%00kind%3ANormal%00%00empty%3Ax%00%00ratio%3A1%00",
+            b"_TS%3A1%00",
+        ];
+        for input in inputs {
+            let result: Result<Typed> = super::from_bytes(input);
+            match result {
+                Err(ref e) => match *e.kind() {
+                    ErrorKind::InvalidInput(_) => {}
+                    _ => panic!("unexpected error: {}", e),
+                },
+                Ok(_) => panic!("accepted malformed input"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        use super::{ErrorKind, Result};
+        use std::collections::BTreeMap;
+        use std::net::Ipv4Addr;
+        // The address is parsed by its visitor, not by the deserializer.
+        let result: Result<BTreeMap<String, Ipv4Addr>> = super::from_bytes(b"%00ip%3Anope%00");
+        match *result.unwrap_err().kind() {
+            ErrorKind::InvalidInput(ref at) => {
+                assert_eq!(at.offset, 8);
+                assert_eq!(at.key, Some("ip".to_owned()));
+            }
+            ref other => panic!("unexpected error: {}", other),
+        }
+    }
 }
@@ -0,0 +1,515 @@
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt::Display;
+
+use crate::revel_deserialize::{Error, ErrorKind, Result};
+
+impl ser::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: Display,
+    {
+        ErrorKind::Message(msg.to_string()).into()
+    }
+}
+
+/// Percent-encode like Go's `url.QueryEscape`, which Revel uses for its
+/// cookies. This is also valid for `application/x-www-form-urlencoded`.
+pub(crate) fn escape(input: &str, output: &mut String) {
+    for &byte in input.as_bytes() {
+        if (byte < 0x80 && (byte as char).is_alphanumeric()) || b"-_.~".contains(&byte) {
+            output.push(byte as char);
+        } else if byte == b' ' {
+            output.push('+');
+        } else {
+            output.push_str(&format!("%{:02X}", byte));
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Serializer {
+    output: String,
+    key: Option<String>,
+}
+
+impl Serializer {
+    /// Create a revel serializer.
+    pub fn new() -> Self {
+        Serializer {
+            output: String::new(),
+            key: None,
+        }
+    }
+
+    /// Consume the serializer, returning the encoded cookie value.
+    pub fn into_string(self) -> String {
+        self.output
+    }
+
+    fn write_entry(&mut self, key: &str, value: &str) -> Result<()> {
+        // Entries are NUL-delimited, and keys end at the first colon, so
+        // these can't be represented. Values may contain colons.
+        ensure!(
+            !key.contains(':') && !key.contains('\0'),
+            ErrorKind::ReservedCharacter(key.to_owned())
+        );
+        ensure!(!value.contains('\0'), ErrorKind::ReservedCharacter(value.to_owned()));
+        self.output.push_str("%00");
+        escape(key, &mut self.output);
+        self.output.push_str("%3A");
+        escape(value, &mut self.output);
+        self.output.push_str("%00");
+        Ok(())
+    }
+}
+
+fn top_level<T>() -> Result<T> {
+    bail!(ErrorKind::Unsupported("top-level value must be a map or struct"))
+}
+
+impl ser::Serializer for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_char(self, _v: char) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        top_level()
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        top_level()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        top_level()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        top_level()
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        top_level()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        top_level()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        top_level()
+    }
+}
+
+impl ser::SerializeMap for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = key.serialize(ScalarSerializer)?
+            .ok_or(ErrorKind::Unsupported("keys must not be `None`"))?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        // `None` values are left out, and deserialize as `None` again.
+        if let Some(value) = value.serialize(ScalarSerializer)? {
+            self.write_entry(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Some(value) = value.serialize(ScalarSerializer)? {
+            self.write_entry(key, &value)?;
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Renders a key or a value, with `None` meaning that the entry is absent.
+struct ScalarSerializer;
+
+fn scalar<T: Display>(v: T) -> Result<Option<String>> {
+    Ok(Some(v.to_string()))
+}
+
+fn not_scalar<T>() -> Result<T> {
+    bail!(ErrorKind::Unsupported("keys and values must be scalars"))
+}
+
+impl ser::Serializer for ScalarSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Option<String>, Error>;
+    type SerializeTuple = Impossible<Option<String>, Error>;
+    type SerializeTupleStruct = Impossible<Option<String>, Error>;
+    type SerializeTupleVariant = Impossible<Option<String>, Error>;
+    type SerializeMap = Impossible<Option<String>, Error>;
+    type SerializeStruct = Impossible<Option<String>, Error>;
+    type SerializeStructVariant = Impossible<Option<String>, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Option<String>> {
+        scalar(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<String>> {
+        match ::std::str::from_utf8(v) {
+            Ok(s) => scalar(s),
+            Err(_) => bail!(ErrorKind::Unsupported("bytes must be valid UTF-8")),
+        }
+    }
+
+    fn serialize_none(self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Option<String>>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<String>> {
+        scalar("")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<String>> {
+        scalar("")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Option<String>> {
+        scalar(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Option<String>>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Option<String>>
+    where
+        T: ?Sized + Serialize,
+    {
+        not_scalar()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        not_scalar()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        not_scalar()
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        not_scalar()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        not_scalar()
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        not_scalar()
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        not_scalar()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        not_scalar()
+    }
+}
+
+/// Encode a map or struct into a cookie value that `from_bytes` decodes back
+/// into the same value.
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let mut serializer = Serializer::new();
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use crate::revel_deserialize::{from_bytes, RevelFlash};
+
+    #[test]
+    fn test_serialize() {
+        let mut input = BTreeMap::new();
+        input.insert("a", "b c");
+        input.insert("d", "%&");
+        assert_eq!(super::to_string(&input).unwrap(), "%00a%3Ab+c%00%00d%3A%25%26%00");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input: HashMap<_, _> = [("UserScreenName", "chokudai"), ("_TS", "1510000000"), ("e", "")]
+            .iter()
+            .map(|&(x, y)| (x.to_owned(), y.to_owned()))
+            .collect();
+        let encoded = super::to_string(&input).unwrap();
+        let decoded: HashMap<String, String> = from_bytes(encoded.as_bytes()).unwrap();
+        assert_eq!(input, decoded);
+
+        let flash = RevelFlash {
+            success: None,
+            error: Some("ユーザ名またはパスワードが正しくありません".to_owned()),
+        };
+        let encoded = super::to_string(&flash).unwrap();
+        assert_eq!(flash, from_bytes(encoded.as_bytes()).unwrap());
+
+        let flash = RevelFlash {
+            success: Some("The contest starts at 21:00.".to_owned()),
+            error: None,
+        };
+        let encoded = super::to_string(&flash).unwrap();
+        assert_eq!(flash, from_bytes(encoded.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_reserved() {
+        let mut input = BTreeMap::new();
+        input.insert("a:b", "c");
+        assert!(super::to_string(&input).is_err());
+        let mut input = BTreeMap::new();
+        input.insert("a", "b\0c");
+        assert!(super::to_string(&input).is_err());
+        assert!(super::to_string(&"a").is_err());
+    }
+}
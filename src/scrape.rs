@@ -0,0 +1,104 @@
+//! Errors for pages that don't have the layout the scrapers expect, with
+//! enough context to locate the change without patching the crate.
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::path::PathBuf;
+use std::process;
+
+use crate::{Error, ErrorKind, Result};
+use crate::store::write_private;
+
+/// Where and why scraping a page failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScrapeError {
+    /// What was expected, e.g. `code size ending with " Byte"`.
+    pub expected: String,
+    /// The page being scraped, if it was fetched rather than passed in.
+    pub url: Option<String>,
+    /// A CSS selector for the element that failed, e.g. `td:nth-of-type(6)`.
+    pub selector: String,
+    /// The row of a table, counted from 1.
+    pub row: Option<usize>,
+    /// The text that failed to parse.
+    pub text: Option<String>,
+    /// Where the page was saved, if `Config::dump_html` is set.
+    pub dump: Option<PathBuf>,
+}
+
+impl ScrapeError {
+    pub fn new<S: Into<String>>(selector: S, expected: &str) -> ScrapeError {
+        ScrapeError {
+            expected: expected.to_owned(),
+            url: None,
+            selector: selector.into(),
+            row: None,
+            text: None,
+            dump: None,
+        }
+    }
+
+    pub fn row(mut self, row: usize) -> ScrapeError {
+        self.row = Some(row);
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> ScrapeError {
+        self.text = Some(text.to_owned());
+        self
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {} at `{}`", self.expected, self.selector)?;
+        if let Some(row) = self.row {
+            write!(f, " in row {}", row)?;
+        }
+        if let Some(ref text) = self.text {
+            write!(f, ", found {:?}", text)?;
+        }
+        if let Some(ref url) = self.url {
+            write!(f, " on {}", url)?;
+        }
+        if let Some(ref dump) = self.dump {
+            write!(f, " (page saved to {})", dump.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ScrapeError {}
+
+/// A page being scraped, used to fill in the context of its errors.
+pub(crate) struct Page<'a> {
+    pub html: &'a str,
+    pub url: Option<&'a str>,
+    pub dump: bool,
+}
+
+impl<'a> Page<'a> {
+    pub fn new(html: &'a str) -> Page<'a> {
+        Page { html, url: None, dump: false }
+    }
+
+    pub fn error(&self, mut error: ScrapeError) -> Error {
+        error.url = self.url.map(|url| url.to_owned());
+        if self.dump {
+            // Failing to save the page shouldn't hide the original error.
+            error.dump = self.save().ok();
+        }
+        ErrorKind::Scrape(error).into()
+    }
+
+    fn save(&self) -> Result<PathBuf> {
+        // Pages of a session hold its CSRF token, so only the owner may read
+        // them.
+        let now = time::get_time();
+        let name = format!("atcoder-{}-{}-{}.html", process::id(), now.sec, now.nsec);
+        let path = env::temp_dir().join(name);
+        write_private(&path, self.html.as_bytes())?;
+        Ok(path)
+    }
+}
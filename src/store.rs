@@ -0,0 +1,199 @@
+//! A file for saving sessions, optionally encrypted with a passphrase or
+//! the contents of a key file.
+//!
+//! Plain stores are JSON. Encrypted ones hold the same JSON sealed with
+//! ChaCha20-Poly1305, under a key derived from the secret with Argon2.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use argon2::Argon2;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, OsRng};
+use chacha20poly1305::aead::rand_core::RngCore;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::{ErrorKind, Result};
+
+/// Starts every encrypted store, followed by the salt, nonce and ciphertext.
+const MAGIC: &[u8] = b"ATCODER-STORE\x01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+pub struct Store {
+    path: PathBuf,
+    secret: Option<Vec<u8>>,
+    /// Where the secret was read from, to check its permissions.
+    key_file: Option<PathBuf>,
+}
+
+impl Store {
+    pub fn plain<P: Into<PathBuf>>(path: P) -> Store {
+        Store {
+            path: path.into(),
+            secret: None,
+            key_file: None,
+        }
+    }
+
+    pub fn encrypted<P: Into<PathBuf>>(path: P, secret: &[u8]) -> Store {
+        //! A store encrypted with `secret`, e.g. a passphrase or the
+        //! contents of a key file. Plain files are still read, and are
+        //! encrypted when next saved.
+        Store {
+            path: path.into(),
+            secret: Some(secret.to_owned()),
+            key_file: None,
+        }
+    }
+
+    pub fn key_file<P: Into<PathBuf>>(path: P, key_file: &Path) -> Result<Store> {
+        //! A store encrypted with the contents of `key_file`.
+        let mut secret = vec![];
+        File::open(key_file)?.read_to_end(&mut secret)?;
+        Ok(Store {
+            key_file: Some(key_file.to_owned()),
+            ..Store::encrypted(path, &secret)
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn check_permissions(&self) -> Result<()> {
+        //! Fail with `InsecurePermissions` if users other than the owner can
+        //! access the file, or the key file it is encrypted with. Always
+        //! succeeds on platforms without Unix permissions, and for files
+        //! that don't exist yet.
+        check_private(&self.path)?;
+        match self.key_file {
+            Some(ref key_file) => check_private(key_file),
+            None => Ok(()),
+        }
+    }
+
+    pub fn load<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        //! Read the store, or `None` if it hasn't been saved yet. Insecure
+        //! permissions are logged as a warning.
+        if let Err(e) = self.check_permissions() {
+            warn!("{}", e);
+        }
+        let mut data = vec![];
+        match File::open(&self.path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            file => file?.read_to_end(&mut data)?,
+        };
+        let json = match data.strip_prefix(MAGIC) {
+            Some(sealed) => {
+                let secret = self.secret.as_ref().ok_or(ErrorKind::StoreEncrypted)?;
+                ensure!(sealed.len() >= SALT_LEN + NONCE_LEN, ErrorKind::WrongKey);
+                let (salt, sealed) = sealed.split_at(SALT_LEN);
+                let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+                cipher(secret, salt)?
+                    .decrypt(Nonce::from_slice(nonce), ciphertext)
+                    .map_err(|_| ErrorKind::WrongKey)?
+            }
+            None => data,
+        };
+        Ok(Some(serde_json::from_slice(&json)?))
+    }
+
+    pub fn save<T: Serialize>(&self, value: &T) -> Result<()> {
+        //! Write the store, readable and writable by the owner only. The
+        //! previous contents are replaced atomically, so they survive a
+        //! failed save.
+        let json = serde_json::to_vec(value)?;
+        let data = match self.secret {
+            Some(ref secret) => {
+                let mut salt = [0; SALT_LEN];
+                let mut nonce = [0; NONCE_LEN];
+                OsRng.fill_bytes(&mut salt);
+                OsRng.fill_bytes(&mut nonce);
+                let ciphertext = cipher(secret, &salt)?
+                    .encrypt(Nonce::from_slice(&nonce), &*json)
+                    .map_err(|_| "Failed to encrypt the store")?;
+                [MAGIC, &salt, &nonce, &ciphertext].concat()
+            }
+            None => json,
+        };
+        write_private(&self.path, &data)
+    }
+}
+
+fn cipher(secret: &[u8], salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|e| format!("Failed to derive the key: {}", e))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn check_private(path: &Path) -> Result<()> {
+    let metadata = match fs::metadata(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        result => result?,
+    };
+    let mode = mode(&metadata);
+    ensure!(
+        mode & 0o077 == 0,
+        ErrorKind::InsecurePermissions(path.to_owned(), mode)
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o777
+}
+
+#[cfg(not(unix))]
+fn mode(_: &fs::Metadata) -> u32 {
+    0o600
+}
+
+/// Tells apart the temporary files of one process.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    //! Replace the file at `path` with `data`, readable and writable by the
+    //! owner only. The data is written and synced to a sibling file first,
+    //! which is renamed over `path`, so neither a crash nor a concurrent
+    //! reader ever sees it half-written.
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut name = path.file_name().ok_or("The path has no file name")?.to_owned();
+    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    name.push(format!(".{}-{}.tmp", process::id(), counter));
+    let temp = path.with_file_name(name);
+    let result = create(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| Ok(fs::rename(&temp, path)?));
+    if result.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    result
+}
+
+#[cfg(unix)]
+fn create(path: &Path) -> Result<File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // The mode above only applies to new files.
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create(path: &Path) -> Result<File> {
+    Ok(OpenOptions::new().write(true).create(true).truncate(true).open(path)?)
+}
@@ -0,0 +1,56 @@
+//! Logging of every HTTP exchange, to find out which request of an
+//! operation failed and where it was redirected.
+//!
+//! Each exchange is logged at `info` with its method, URL, status, latency,
+//! redirect target and the names of the cookies it set. Headers follow at
+//! `trace`, with cookie values redacted as in cassettes.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::transport::{Request, ResponseFuture, Transport};
+use crate::vcr::scrub_headers;
+
+fn cookie_name(cookie: &str) -> &str {
+    cookie.split(['=', ';']).next().unwrap_or("").trim()
+}
+
+/// Passes requests on to another transport, logging them and their result.
+pub struct TraceTransport {
+    inner: Arc<dyn Transport>,
+}
+
+impl TraceTransport {
+    pub fn new(inner: Arc<dyn Transport>) -> TraceTransport {
+        TraceTransport { inner }
+    }
+}
+
+impl Transport for TraceTransport {
+    fn send(&self, request: Request) -> ResponseFuture {
+        let method = request.method;
+        let url = request.url.clone();
+        trace!("{} {} request headers: {:?}", method, url, scrub_headers(&request.headers));
+        let future = self.inner.send(request);
+        Box::pin(async move {
+            let start = Instant::now();
+            let result = future.await;
+            let elapsed = start.elapsed().as_millis();
+            match result {
+                Ok(ref response) => {
+                    let location = response
+                        .header("Location")
+                        .map_or(String::new(), |location| format!(" to {}", location));
+                    let cookies: Vec<_> = response.header_values("Set-Cookie").map(cookie_name).collect();
+                    info!(
+                        "{} {} {}{} in {}ms, set cookies {:?}",
+                        method, url, response.status, location, elapsed, cookies
+                    );
+                    trace!("{} {} response headers: {:?}", method, url, scrub_headers(&response.headers));
+                }
+                Err(ref e) => warn!("{} {} failed after {}ms: {}", method, url, elapsed, e),
+            }
+            result
+        })
+    }
+}
@@ -0,0 +1,214 @@
+//! The HTTP layer underneath `Client`, abstracted so that requests can be
+//! served without a network.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::{self, Future};
+use std::io;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+use reqwest::Client as HttpClient;
+use reqwest::{StatusCode, Url};
+
+use crate::Result;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: Method,
+    pub url: Url,
+    /// Header names and values, in the order they are sent.
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        //! The value of the first header named `name`, case-insensitively.
+        find_header(&self.headers, name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    /// Header names and values. Repeated headers, like `Set-Cookie`, appear
+    /// once per value.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: StatusCode) -> Response {
+        Response {
+            status,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, body: B) -> Response {
+        self.body = body.into();
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        //! The value of the first header named `name`, case-insensitively.
+        find_header(&self.headers, name)
+    }
+
+    pub fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a str> + 'a {
+        //! Every value of the headers named `name`, case-insensitively.
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &**v)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| &**v)
+}
+
+pub type ResponseFuture = Pin<Box<dyn Future<Output=Result<Response>> + Send>>;
+
+/// Sends requests on behalf of a `Client`. Implementations must not follow
+/// redirects, as the API inspects them.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: Request) -> ResponseFuture;
+}
+
+/// The default transport, talking HTTP through reqwest.
+pub struct ReqwestTransport {
+    client: HttpClient,
+    read_timeout: Option<Duration>,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: HttpClient) -> ReqwestTransport {
+        ReqwestTransport { client, read_timeout: None }
+    }
+
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> ReqwestTransport {
+        //! Fail with `io::ErrorKind::TimedOut` when the server sends nothing
+        //! for `timeout`, rather than waiting for it forever.
+        self.read_timeout = timeout;
+        self
+    }
+}
+
+async fn read<F: Future>(timeout: Option<Duration>, future: F) -> Result<F::Output> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "read timed out").into()),
+        None => Ok(future.await),
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: Request) -> ResponseFuture {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+        };
+        let mut builder = self.client.request(method, request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(&**name, &**value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        let timeout = self.read_timeout;
+        Box::pin(async move {
+            let mut response = read(timeout, builder.send()).await??;
+            let status = response.status();
+            // Repeated headers, like `Set-Cookie`, are yielded once per value.
+            let headers = response
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (name.as_str().to_owned(), String::from_utf8_lossy(value.as_bytes()).into_owned())
+                })
+                .collect();
+            let mut body = vec![];
+            while let Some(chunk) = read(timeout, response.chunk()).await?? {
+                body.extend_from_slice(&chunk);
+            }
+            Ok(Response { status, headers, body })
+        })
+    }
+}
+
+/// Serves canned responses keyed by method and URL, and records the requests
+/// it receives. Meant for tests.
+#[derive(Default)]
+pub struct MemoryTransport {
+    responses: Mutex<HashMap<(Method, String), VecDeque<Response>>>,
+    requests: Mutex<Vec<Request>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        Default::default()
+    }
+
+    pub fn push(&self, method: Method, url: &str, response: Response) {
+        //! Queue a response. Responses for the same request are served in
+        //! order, and the last one is repeated.
+        self.responses
+            .lock()
+            .unwrap()
+            .entry((method, url.to_owned()))
+            .or_default()
+            .push_back(response);
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        //! Every request received so far.
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn respond(&self, request: &Request) -> Result<Response> {
+        let mut responses = self.responses.lock().unwrap();
+        let queue = responses
+            .get_mut(&(request.method, request.url.as_str().to_owned()))
+            .ok_or_else(|| format!("No response for {:?} {}", request.method, request.url))?;
+        Ok(if queue.len() > 1 {
+            queue.pop_front().unwrap()
+        } else {
+            queue[0].clone()
+        })
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&self, request: Request) -> ResponseFuture {
+        let response = self.respond(&request);
+        self.requests.lock().unwrap().push(request);
+        Box::pin(future::ready(response))
+    }
+}
@@ -0,0 +1,213 @@
+//! Record and replay of HTTP exchanges, so that a real session can be
+//! captured once and used as an offline regression test.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use reqwest::{StatusCode, Url};
+
+use crate::{ErrorKind, Result};
+use crate::transport::{Method, Request, Response, ResponseFuture, Transport};
+
+/// Replaces secrets in a cassette.
+const SCRUBBED: &str = "SCRUBBED";
+/// Form fields that are scrubbed from recorded requests.
+const SECRET_FIELDS: &[&str] = &["password"];
+/// Cookies kept as-is, as they hold the messages the API returns.
+const PUBLIC_COOKIES: &[&str] = &["REVEL_FLASH"];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+fn scrub_cookie(cookie: &str) -> String {
+    let (pair, attributes) = match cookie.find(';') {
+        Some(semicolon) => cookie.split_at(semicolon),
+        None => (cookie, ""),
+    };
+    match pair.find('=') {
+        Some(equal) if !PUBLIC_COOKIES.contains(&pair[..equal].trim()) => {
+            format!("{}={}{}", &pair[..equal], SCRUBBED, attributes)
+        }
+        _ => cookie.to_owned(),
+    }
+}
+
+pub(crate) fn scrub_headers(headers: &[(String, String)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if name.eq_ignore_ascii_case("Cookie") {
+                value.split("; ").map(scrub_cookie).collect::<Vec<_>>().join("; ")
+            } else if name.eq_ignore_ascii_case("Set-Cookie") {
+                scrub_cookie(value)
+            } else {
+                value.clone()
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+fn scrub_form(body: &str) -> String {
+    body.split('&')
+        .map(|field| match field.find('=') {
+            Some(equal) if SECRET_FIELDS.contains(&&field[..equal]) => {
+                format!("{}={}", &field[..equal], SCRUBBED)
+            }
+            _ => field.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+impl Interaction {
+    fn scrubbed(request: &Request, response: &Response) -> Interaction {
+        Interaction {
+            request: RecordedRequest {
+                method: request.method,
+                url: request.url.as_str().to_owned(),
+                headers: scrub_headers(&request.headers),
+                body: request.body.as_ref().map(|body| scrub_form(body)),
+            },
+            response: RecordedResponse {
+                status: response.status.as_u16(),
+                headers: scrub_headers(&response.headers),
+                body: String::from_utf8_lossy(&response.body).into_owned(),
+            },
+        }
+    }
+}
+
+pub fn load_cassette(path: &Path) -> Result<Vec<Interaction>> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
+
+pub fn save_cassette(path: &Path, interactions: &[Interaction]) -> Result<()> {
+    Ok(serde_json::to_writer_pretty(File::create(path)?, interactions)?)
+}
+
+/// Passes requests on to another transport, saving every exchange with
+/// secrets scrubbed. The cassette is rewritten after each exchange, so that
+/// an interrupted session is still captured.
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    path: PathBuf,
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn Transport>, path: PathBuf) -> RecordingTransport {
+        RecordingTransport {
+            inner,
+            path,
+            interactions: Default::default(),
+        }
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: Request) -> ResponseFuture {
+        let path = self.path.clone();
+        let interactions = self.interactions.clone();
+        let recorded = request.clone();
+        let response = self.inner.send(request);
+        Box::pin(async move {
+            let response = response.await?;
+            let mut interactions = interactions.lock().unwrap();
+            interactions.push(Interaction::scrubbed(&recorded, &response));
+            save_cassette(&path, &interactions)?;
+            Ok(response)
+        })
+    }
+}
+
+/// Serves the exchanges of a cassette in the order they were recorded,
+/// without touching the network.
+pub struct ReplayTransport {
+    interactions: Mutex<VecDeque<Interaction>>,
+}
+
+impl ReplayTransport {
+    pub fn new(interactions: Vec<Interaction>) -> ReplayTransport {
+        ReplayTransport { interactions: Mutex::new(interactions.into()) }
+    }
+
+    pub fn load(path: &Path) -> Result<ReplayTransport> {
+        Ok(ReplayTransport::new(load_cassette(path)?))
+    }
+
+    fn replay(&self, request: &Request) -> Result<Response> {
+        let interaction = self.interactions.lock().unwrap().pop_front().ok_or_else(|| {
+            ErrorKind::CassetteMismatch(format!(
+                "no recorded exchange left for {:?} {}",
+                request.method,
+                request.url
+            ))
+        })?;
+        let recorded = interaction.request;
+        ensure!(
+            recorded.method == request.method && Url::parse(&recorded.url)? == request.url,
+            ErrorKind::CassetteMismatch(format!(
+                "expected {:?} {}, got {:?} {}",
+                recorded.method,
+                recorded.url,
+                request.method,
+                request.url
+            ))
+        );
+        let response = interaction.response;
+        let status = StatusCode::from_u16(response.status).map_err(|_| {
+            ErrorKind::CassetteMismatch(format!("invalid status {}", response.status))
+        })?;
+        Ok(Response {
+            status,
+            headers: response.headers,
+            body: response.body.into_bytes(),
+        })
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: Request) -> ResponseFuture {
+        Box::pin(future::ready(self.replay(&request)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_scrub() {
+        assert_eq!(
+            super::scrub_form("username=chokudai&password=hunter2&csrf_token=abc"),
+            "username=chokudai&password=SCRUBBED&csrf_token=abc"
+        );
+        assert_eq!(
+            super::scrub_cookie("REVEL_SESSION=abc-%00a%3Ab%00; Path=/; HttpOnly"),
+            "REVEL_SESSION=SCRUBBED; Path=/; HttpOnly"
+        );
+        assert_eq!(
+            super::scrub_cookie("REVEL_FLASH=%00success%3AOK%00; Path=/"),
+            "REVEL_FLASH=%00success%3AOK%00; Path=/"
+        );
+    }
+}
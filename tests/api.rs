@@ -1,62 +1,46 @@
-extern crate atcoder;
-extern crate tokio_core;
-extern crate futures;
-
 use std::env;
-use tokio_core::reactor::Core;
-use futures::Future;
+use atcoder::{Config, Session};
+
+async fn login() -> Session {
+    let client = atcoder::create_client(Config::default()).unwrap();
+    Session::login(
+        &env::var("ATCODER_USERNAME").unwrap(),
+        &env::var("ATCODER_PASSWORD").unwrap(),
+        &client,
+    ).await
+        .unwrap()
+        .0
+}
 
-#[test]
+#[tokio::test]
 #[ignore]
-fn test_login_logout() {
-    let mut core = Core::new().unwrap();
-    let client = atcoder::create_client(&core.handle()).unwrap();
-    core.run(
-        atcoder::login(
-            &env::var("ATCODER_USERNAME").unwrap(),
-            &env::var("ATCODER_PASSWORD").unwrap(),
-            &client,
-        ).and_then(|(auth, _)| atcoder::logout(auth, &client)),
-    ).unwrap();
+async fn test_login_logout() {
+    let session = login().await;
+    session.logout().await.unwrap();
 }
 
-#[test]
+#[tokio::test]
 #[ignore]
-fn test_join() {
-    let mut core = Core::new().unwrap();
-    let client = atcoder::create_client(&core.handle()).unwrap();
-    core.run(
-        atcoder::login(
-            &env::var("ATCODER_USERNAME").unwrap(),
-            &env::var("ATCODER_PASSWORD").unwrap(),
-            &client,
-        ).and_then(|(auth, _)| {
-            atcoder::join(&env::var("ATCODER_CONTEST_JOIN").unwrap(), auth, &client)
-        })
-            .and_then(|(_, auth)| atcoder::logout(auth, &client)),
-    ).unwrap();
+async fn test_join() {
+    let session = login().await;
+    session.join(&env::var("ATCODER_CONTEST_JOIN").unwrap().parse().unwrap()).await.unwrap();
+    session.logout().await.unwrap();
 }
 
-#[test]
+#[tokio::test]
 #[ignore]
-fn test_submit() {
-    let mut core = Core::new().unwrap();
-    let client = atcoder::create_client(&core.handle()).unwrap();
-    core.run(
-        atcoder::login(
-            &env::var("ATCODER_USERNAME").unwrap(),
-            &env::var("ATCODER_PASSWORD").unwrap(),
-            &client,
-        ).and_then(|(auth, _)| {
-            atcoder::submit(
-                "practice",
-                "a",
-                "rust",
-                include_str!("submit_data/practice_a.rs").to_owned(),
-                auth,
-                &client,
-            )
-        })
-            .and_then(|(_, auth)| atcoder::logout(auth, &client)),
-    ).unwrap();
+async fn test_submit() {
+    let session = login().await;
+    let contest = "practice".parse().unwrap();
+    let (task, lang) = session.resolve_submit(&contest, "a", "rust").await.unwrap();
+    session
+        .submit(
+            &contest,
+            &task,
+            &lang,
+            include_str!("submit_data/practice_a.rs").to_owned(),
+        )
+        .await
+        .unwrap();
+    session.logout().await.unwrap();
 }
@@ -0,0 +1,30 @@
+mod support;
+
+use atcoder::Config;
+use atcoder::blocking::{Client, Session};
+use support::FakeAtCoder;
+
+#[test]
+fn test_flow() {
+    let server = FakeAtCoder::start();
+    server.add_user("chokudai", "password");
+    let client = Client::new(Config { base_url: server.base_url(), ..Config::default() }).unwrap();
+
+    let (session, _) = Session::login("chokudai", "password", &client).unwrap();
+    session.join(&"practice".parse().unwrap()).unwrap();
+    let contest = "practice".parse().unwrap();
+    let (task, lang) = session.resolve_submit(&contest, "a", "rust").unwrap();
+    session.submit(
+        &contest,
+        &task,
+        &lang,
+        include_str!("submit_data/practice_a.rs").to_owned(),
+    ).unwrap();
+    let submissions = session.submissions(&"practice".parse().unwrap()).unwrap();
+    assert_eq!(submissions.len(), 1);
+    assert_eq!(submissions[0].task, "A - Welcome to AtCoder");
+
+    // Clones share the runtime and the session cookie.
+    let resumed = Session::new(session.client(), session.authentication());
+    assert_eq!(resumed.logout().unwrap().unwrap().text, "Signed out.");
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use reqwest::{StatusCode, Url};
+use atcoder::cache::{Cache, CacheTransport};
+use atcoder::revel_serialize;
+use atcoder::transport::{MemoryTransport, Method, Request, Response, Transport};
+
+const URL: &str = "https://atcoder.test/contests/practice/tasks";
+
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("atcoder-cache-{}-{}", name, process::id()));
+    fs::remove_dir_all(&dir).ok();
+    dir
+}
+
+fn request(user: Option<&str>) -> Request {
+    session_request(user, "token")
+}
+
+fn session_request(user: Option<&str>, token: &str) -> Request {
+    let mut headers = vec![];
+    if let Some(user) = user {
+        let mut fields = BTreeMap::new();
+        fields.insert("UserScreenName", user);
+        fields.insert("csrf_token", token);
+        let session = revel_serialize::to_string(&fields).unwrap();
+        headers.push(("Cookie".to_owned(), format!("REVEL_SESSION=0a1b2c-{}", session)));
+    }
+    Request {
+        method: Method::Get,
+        url: Url::parse(URL).unwrap(),
+        headers,
+        body: None,
+    }
+}
+
+fn page(cache_control: &str, body: &str) -> Response {
+    Response::new(StatusCode::OK)
+        .with_header("Cache-Control", cache_control)
+        .with_header("ETag", "\"v1\"")
+        .with_header("Set-Cookie", "REVEL_SESSION=rotated; Path=/")
+        .with_body(body)
+}
+
+#[tokio::test]
+async fn test_fresh() {
+    let dir = cache_dir("fresh");
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(Method::Get, URL, page("max-age=60", "tasks"));
+    let cache = CacheTransport::new(memory.clone(), Cache::new(&dir));
+    cache.send(request(Some("chokudai"))).await.unwrap();
+    let response = cache.send(request(Some("chokudai"))).await.unwrap();
+    assert_eq!(response.body, b"tasks");
+    // The session cookie is never replayed from the cache.
+    assert_eq!(response.header("Set-Cookie"), None);
+    assert_eq!(memory.requests().len(), 1);
+
+    // Entries are kept per session, even of the same user.
+    cache.send(request(Some("rng_58"))).await.unwrap();
+    assert_eq!(memory.requests().len(), 2);
+    cache.send(session_request(Some("chokudai"), "other")).await.unwrap();
+    assert_eq!(memory.requests().len(), 3);
+
+    let refresh = CacheTransport::new(memory.clone(), Cache { refresh: true, ..Cache::new(&dir) });
+    refresh.send(request(Some("chokudai"))).await.unwrap();
+    assert_eq!(memory.requests().len(), 4);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_revalidate() {
+    let dir = cache_dir("revalidate");
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(Method::Get, URL, page("no-cache", "tasks"));
+    memory.push(
+        Method::Get,
+        URL,
+        Response::new(StatusCode::NOT_MODIFIED).with_header("Set-Cookie", "REVEL_SESSION=new; Path=/"),
+    );
+    let cache = CacheTransport::new(memory.clone(), Cache::new(&dir));
+    cache.send(request(Some("chokudai"))).await.unwrap();
+    let response = cache.send(request(Some("chokudai"))).await.unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, b"tasks");
+    assert_eq!(response.header("Set-Cookie"), Some("REVEL_SESSION=new; Path=/"));
+    let requests = memory.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].header("If-None-Match"), Some("\"v1\""));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_not_cached() {
+    let dir = cache_dir("not-cached");
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(Method::Get, URL, page("no-store, max-age=60", "tasks"));
+    let cache = CacheTransport::new(memory.clone(), Cache::new(&dir));
+    cache.send(request(Some("chokudai"))).await.unwrap();
+    cache.send(request(Some("chokudai"))).await.unwrap();
+    assert_eq!(memory.requests().len(), 2);
+
+    // Requests without a session aren't cached either.
+    memory.push(Method::Get, URL, page("max-age=60", "tasks"));
+    cache.send(request(None)).await.unwrap();
+    cache.send(request(None)).await.unwrap();
+    assert_eq!(memory.requests().len(), 4);
+    assert!(!dir.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = cache_dir("permissions");
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(Method::Get, URL, page("max-age=60", "tasks"));
+    let cache = CacheTransport::new(memory.clone(), Cache::new(&dir));
+    cache.send(request(Some("chokudai"))).await.unwrap();
+    let entries: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].metadata().unwrap().permissions().mode() & 0o777, 0o600);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,128 @@
+mod support;
+
+use std::io;
+use std::net::TcpListener;
+use std::time::Duration;
+use atcoder::{create_client, Client, Config, ErrorKind, Session};
+use atcoder::locale::Locale;
+use atcoder::retry::RetryPolicy;
+use support::FakeAtCoder;
+
+fn setup() -> (FakeAtCoder, Client) {
+    let server = FakeAtCoder::start();
+    server.add_user("chokudai", "password");
+    let config = Config { base_url: server.base_url(), ..Config::default() };
+    let client = create_client(config).unwrap();
+    (server, client)
+}
+
+#[tokio::test]
+async fn test_flow() {
+    let (server, client) = setup();
+    let (session, message) = Session::login("chokudai", "password", &client).await.unwrap();
+    assert_eq!(message.unwrap().text, "Welcome, chokudai.");
+    assert_eq!(
+        session.authentication().unwrap().session_info().unwrap().username,
+        Some("chokudai".to_owned())
+    );
+
+    session.join(&"practice".parse().unwrap()).await.unwrap();
+    let contest = "practice".parse().unwrap();
+    let (task, lang) = session.resolve_submit(&contest, "a", "rust").await.unwrap();
+    assert_eq!((task.as_str(), lang.as_str()), ("practice_1", "3504"));
+    session.submit(
+        &contest,
+        &task,
+        &lang,
+        include_str!("submit_data/practice_a.rs").to_owned(),
+    ).await.unwrap();
+    let submitted = server.submissions();
+    assert_eq!(submitted.len(), 1);
+    assert_eq!(submitted[0].task, "practice_1");
+    assert_eq!(submitted[0].language, "3504");
+    assert_eq!(submitted[0].source, include_str!("submit_data/practice_a.rs"));
+
+    let submissions = session.submissions(&"practice".parse().unwrap()).await.unwrap();
+    assert_eq!(submissions.len(), 1);
+    assert_eq!(submissions[0].id.as_str(), "1");
+    assert_eq!(submissions[0].task, "A - Welcome to AtCoder");
+    assert_eq!(submissions[0].code_length, submitted[0].source.len());
+
+    assert_eq!(session.logout().await.unwrap().unwrap().text, "Signed out.");
+    assert_eq!(session.authentication().unwrap().session_info().unwrap().username, None);
+}
+
+#[tokio::test]
+async fn test_wrong_password() {
+    let (_server, client) = setup();
+    let error = Session::login("chokudai", "wrong", &client).await.err().unwrap();
+    match *error.kind() {
+        ErrorKind::Unauthorized(ref flash) => {
+            assert_eq!(flash.text, "Username or Password is incorrect.");
+        }
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_join_twice() {
+    let (_server, client) = setup();
+    let (session, _) = Session::login("chokudai", "password", &client).await.unwrap();
+    session.join(&"practice".parse().unwrap()).await.unwrap();
+    match *session.join(&"practice".parse().unwrap()).await.err().unwrap().kind() {
+        ErrorKind::Unauthorized(ref flash) => assert_eq!(flash.text, "You are already registered."),
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_login_required() {
+    let (_server, client) = setup();
+    let session = Session::new(&client, None);
+    match *session.submissions(&"practice".parse().unwrap()).await.err().unwrap().kind() {
+        ErrorKind::SessionExpired => {}
+        ref other => panic!("unexpected error: {}", other),
+    }
+
+    let session = session.with_credentials(|| Ok(("chokudai".to_owned(), "password".to_owned())));
+    assert!(session.submissions(&"practice".parse().unwrap()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_read_timeout() {
+    // Accepts connections but never answers.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = Config {
+        base_url: format!("http://{}", listener.local_addr().unwrap()),
+        retry: RetryPolicy::none(),
+        read_timeout: Some(Duration::from_millis(100)),
+        ..Config::default()
+    };
+    let client = create_client(config).unwrap();
+    match *Session::login("chokudai", "password", &client).await.err().unwrap().kind() {
+        ErrorKind::IoError(ref e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_locale() {
+    let server = FakeAtCoder::start();
+    server.add_user("chokudai", "password");
+    let config = Config {
+        base_url: server.base_url(),
+        locale: Some(Locale::Japanese),
+        ..Config::default()
+    };
+    let client = create_client(config).unwrap();
+    match *Session::login("chokudai", "wrong", &client).await.err().unwrap().kind() {
+        ErrorKind::Unauthorized(ref flash) => {
+            assert_eq!(flash.text, "ユーザ名またはパスワードが正しくありません。");
+        }
+        ref other => panic!("unexpected error: {}", other),
+    }
+    let (session, message) = Session::login("chokudai", "password", &client).await.unwrap();
+    assert_eq!(message.unwrap().text, "ようこそ、chokudai さん。");
+    session.join(&"practice".parse().unwrap()).await.unwrap();
+    assert_eq!(session.submissions(&"practice".parse().unwrap()).await.unwrap().len(), 0);
+}
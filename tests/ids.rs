@@ -0,0 +1,45 @@
+use atcoder::{create_client, Config, ErrorKind};
+use atcoder::locale::Locale;
+use atcoder::ids::{ContestId, LanguageId, SubmissionId, TaskScreenName};
+
+#[test]
+fn test_parse() {
+    let contest: ContestId = "code-festival-2017-quala".parse().unwrap();
+    assert_eq!(contest.to_string(), "code-festival-2017-quala");
+    let task: TaskScreenName = "abc001_1".parse().unwrap();
+    assert_eq!(task.as_str(), "abc001_1");
+    assert!("5054".parse::<LanguageId>().is_ok());
+
+    for invalid in &["", "abc001/", "abc 001", "../practice"] {
+        match *invalid.parse::<ContestId>().unwrap_err().kind() {
+            ErrorKind::InvalidId(_, ref value) => assert_eq!(value, invalid),
+            ref other => panic!("unexpected error: {}", other),
+        }
+    }
+    assert!("rust".parse::<LanguageId>().is_err());
+    assert!("-1".parse::<SubmissionId>().is_err());
+}
+
+#[test]
+fn test_serde() {
+    let id: SubmissionId = serde_json::from_str("\"1749520\"").unwrap();
+    assert_eq!(serde_json::to_string(&id).unwrap(), "\"1749520\"");
+    assert!(serde_json::from_str::<SubmissionId>("\"1749520/\"").is_err());
+}
+
+#[test]
+fn test_path() {
+    let contest: ContestId = "abc001".parse().unwrap();
+    assert_eq!(contest.path(), "/contests/abc001/");
+    assert_eq!(contest.page_path("submit/"), "/contests/abc001/submit/");
+    let task: TaskScreenName = "abc001_1".parse().unwrap();
+    assert_eq!(task.path(&contest), "/contests/abc001/tasks/abc001_1");
+    let submission: SubmissionId = "1713284".parse().unwrap();
+    assert_eq!(submission.path(&contest), "/contests/abc001/submissions/1713284");
+
+    let client = create_client(Config { locale: Some(Locale::English), ..Config::default() }).unwrap();
+    assert_eq!(
+        client.url(&task.path(&contest)),
+        "https://atcoder.jp/contests/abc001/tasks/abc001_1?lang=en"
+    );
+}
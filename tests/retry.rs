@@ -0,0 +1,117 @@
+use std::future;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use reqwest::{StatusCode, Url};
+use atcoder::ErrorKind;
+use atcoder::retry::{RetryPolicy, RetryTransport};
+use atcoder::transport::{MemoryTransport, Method, Request, Response, ResponseFuture, Transport};
+
+const URL: &str = "https://atcoder.test/contests/abc001/submit/";
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        jitter: 0.0,
+        ..RetryPolicy::default()
+    }
+}
+
+fn request(method: Method) -> Request {
+    Request {
+        method,
+        url: Url::parse(URL).unwrap(),
+        headers: vec![],
+        body: None,
+    }
+}
+
+/// Fails the first `failures` requests with an IO error, then succeeds.
+struct Flaky {
+    kind: io::ErrorKind,
+    failures: AtomicU32,
+    attempts: AtomicU32,
+}
+
+impl Flaky {
+    fn new(kind: io::ErrorKind, failures: u32) -> Flaky {
+        Flaky {
+            kind,
+            failures: AtomicU32::new(failures),
+            attempts: AtomicU32::new(0),
+        }
+    }
+}
+
+impl Transport for Flaky {
+    fn send(&self, _: Request) -> ResponseFuture {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        let failed = self.failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        Box::pin(future::ready(if failed {
+            Err(io::Error::from(self.kind).into())
+        } else {
+            Ok(Response::new(StatusCode::OK))
+        }))
+    }
+}
+
+#[tokio::test]
+async fn test_get_status() {
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(Method::Get, URL, Response::new(StatusCode::SERVICE_UNAVAILABLE));
+    memory.push(Method::Get, URL, Response::new(StatusCode::BAD_GATEWAY));
+    memory.push(Method::Get, URL, Response::new(StatusCode::OK));
+    let retry = RetryTransport::new(memory.clone(), policy());
+    let response = retry.send(request(Method::Get)).await.unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(memory.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_max_attempts() {
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(Method::Get, URL, Response::new(StatusCode::SERVICE_UNAVAILABLE));
+    let retry = RetryTransport::new(memory.clone(), policy());
+    let response = retry.send(request(Method::Get)).await.unwrap();
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(memory.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_post_status() {
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(Method::Post, URL, Response::new(StatusCode::SERVICE_UNAVAILABLE));
+    memory.push(Method::Post, URL, Response::new(StatusCode::FOUND));
+    let retry = RetryTransport::new(memory.clone(), policy());
+    let response = retry.send(request(Method::Post)).await.unwrap();
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(memory.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_post_connect_error() {
+    let flaky = Arc::new(Flaky::new(io::ErrorKind::ConnectionRefused, 2));
+    let retry = RetryTransport::new(flaky.clone(), policy());
+    retry.send(request(Method::Post)).await.unwrap();
+    assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_post_reset() {
+    let flaky = Arc::new(Flaky::new(io::ErrorKind::ConnectionReset, 1));
+    let retry = RetryTransport::new(flaky.clone(), policy());
+    match *retry.send(request(Method::Post)).await.err().unwrap().kind() {
+        ErrorKind::IoError(ref e) => assert_eq!(e.kind(), io::ErrorKind::ConnectionReset),
+        ref other => panic!("unexpected error: {}", other),
+    }
+    assert_eq!(flaky.attempts.load(Ordering::SeqCst), 1);
+
+    let flaky = Arc::new(Flaky::new(io::ErrorKind::ConnectionReset, 1));
+    let retry = RetryTransport::new(flaky.clone(), policy());
+    retry.send(request(Method::Get)).await.unwrap();
+    assert_eq!(flaky.attempts.load(Ordering::SeqCst), 2);
+}
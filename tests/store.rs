@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use atcoder::ErrorKind;
+use atcoder::store::Store;
+
+fn path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("atcoder-store-{}-{}", name, process::id()));
+    fs::remove_file(&path).ok();
+    path
+}
+
+fn accounts() -> BTreeMap<String, String> {
+    let mut accounts = BTreeMap::new();
+    accounts.insert("main".to_owned(), "REVEL_SESSION=secret".to_owned());
+    accounts
+}
+
+#[test]
+fn test_plain() {
+    let path = path("plain");
+    let store = Store::plain(&path);
+    assert_eq!(store.load::<BTreeMap<String, String>>().unwrap(), None);
+    store.save(&accounts()).unwrap();
+    assert_eq!(store.load().unwrap(), Some(accounts()));
+    store.save(&BTreeMap::<String, String>::new()).unwrap();
+    assert_eq!(store.load().unwrap(), Some(BTreeMap::<String, String>::new()));
+
+    // Saves go through a temporary file, which is gone afterwards.
+    let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+    let siblings = fs::read_dir(env::temp_dir())
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_str().unwrap().starts_with(&name))
+        .count();
+    assert_eq!(siblings, 1);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_encrypted() {
+    let path = path("encrypted");
+    // Plain stores are encrypted when saved with a secret.
+    Store::plain(&path).save(&accounts()).unwrap();
+    let store = Store::encrypted(&path, b"passphrase");
+    assert_eq!(store.load().unwrap(), Some(accounts()));
+    store.save(&accounts()).unwrap();
+    assert!(!String::from_utf8_lossy(&fs::read(&path).unwrap()).contains("secret"));
+    assert_eq!(store.load().unwrap(), Some(accounts()));
+
+    let result = Store::encrypted(&path, b"wrong").load::<BTreeMap<String, String>>();
+    match *result.unwrap_err().kind() {
+        ErrorKind::WrongKey => {}
+        ref other => panic!("unexpected error: {}", other),
+    }
+    let result = Store::plain(&path).load::<BTreeMap<String, String>>();
+    match *result.unwrap_err().kind() {
+        ErrorKind::StoreEncrypted => {}
+        ref other => panic!("unexpected error: {}", other),
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = path("permissions");
+    let store = Store::plain(&path);
+    store.check_permissions().unwrap();
+    store.save(&accounts()).unwrap();
+    store.check_permissions().unwrap();
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+    match *store.check_permissions().unwrap_err().kind() {
+        ErrorKind::InsecurePermissions(_, mode) => assert_eq!(mode, 0o644),
+        ref other => panic!("unexpected error: {}", other),
+    }
+    // Saving restores them.
+    store.save(&accounts()).unwrap();
+    store.check_permissions().unwrap();
+
+    // The key file is checked too.
+    let key_file = self::path("key");
+    fs::write(&key_file, b"key").unwrap();
+    fs::set_permissions(&key_file, fs::Permissions::from_mode(0o640)).unwrap();
+    let store = Store::key_file(&path, &key_file).unwrap();
+    match *store.check_permissions().unwrap_err().kind() {
+        ErrorKind::InsecurePermissions(ref insecure, mode) => {
+            assert_eq!(*insecure, key_file);
+            assert_eq!(mode, 0o640);
+        }
+        ref other => panic!("unexpected error: {}", other),
+    }
+    // Loading only warns.
+    assert_eq!(store.load().unwrap(), Some(accounts()));
+    fs::set_permissions(&key_file, fs::Permissions::from_mode(0o600)).unwrap();
+    store.check_permissions().unwrap();
+    fs::remove_file(&key_file).unwrap();
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,100 @@
+//! Checks the submissions table parser against saved pages. Set
+//! `UPDATE_SNAPSHOTS=1` to rewrite the expected output after a deliberate
+//! change.
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use atcoder::{parse_submissions, ErrorKind, Submission};
+use atcoder::scrape::ScrapeError;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/submissions").join(name)
+}
+
+fn render(submission: &Submission) -> String {
+    format!(
+        "{} | {} | {} | {} | {} | {} | {} Byte | {} | {:?} | {:?}\n",
+        submission.id,
+        submission.timestamp,
+        submission.task,
+        submission.user,
+        submission.lang,
+        submission.score,
+        submission.code_length,
+        submission.status,
+        submission.time,
+        submission.memory
+    )
+}
+
+fn check(name: &str) {
+    let mut html = String::new();
+    File::open(fixture(&format!("{}.html", name)))
+        .unwrap()
+        .read_to_string(&mut html)
+        .unwrap();
+    let actual: String = parse_submissions(&html).unwrap().iter().map(render).collect();
+
+    let snapshot = fixture(&format!("{}.snapshot", name));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        File::create(&snapshot).unwrap().write_all(actual.as_bytes()).unwrap();
+        return;
+    }
+    let mut expected = String::new();
+    File::open(&snapshot).unwrap().read_to_string(&mut expected).unwrap();
+    assert_eq!(expected, actual, "snapshot mismatch for {}", name);
+}
+
+#[test]
+fn test_waiting_for_judge() {
+    check("wj");
+}
+
+#[test]
+fn test_in_progress() {
+    check("in_progress");
+}
+
+#[test]
+fn test_compile_error() {
+    check("compile_error");
+}
+
+#[test]
+fn test_multiple_pages() {
+    check("page1");
+    check("page2");
+}
+
+fn scrape_error(html: &str) -> ScrapeError {
+    match parse_submissions(html).err().unwrap().kind() {
+        ErrorKind::Scrape(e) => e.clone(),
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn test_no_table() {
+    let error = scrape_error("<html><body><p>Not found</p></body></html>");
+    assert_eq!(error.selector, "table tbody");
+    assert_eq!(error.row, None);
+}
+
+#[test]
+fn test_layout_mismatch() {
+    let error = scrape_error(
+        "<table><tbody>\
+         <tr><td>2017-11-05 21:01:52+0900</td><td>A</td><td>chokudai</td><td>Rust</td>\
+         <td>100</td><td>412 Byte</td><td><span>AC</span></td><td>2 ms</td><td>4352 KB</td>\
+         <td><a href=\"/contests/abc001/submissions/1\">Detail</a></td></tr>\
+         <tr><td>2017-11-05 21:01:52+0900</td><td>A</td><td>chokudai</td><td>Rust</td>\
+         <td>100</td><td>0.4 KB</td></tr>\
+         </tbody></table>",
+    );
+    assert_eq!(error.selector, "td:nth-of-type(6)");
+    assert_eq!(error.row, Some(2));
+    assert_eq!(error.text, Some("0.4 KB".to_owned()));
+    assert_eq!(error.url, None);
+}
@@ -0,0 +1,378 @@
+//! A local stand-in for AtCoder, serving just enough of the site over HTTP
+//! for the API to run end-to-end.
+//!
+//! Each test crate uses a different part of it.
+#![allow(dead_code)]
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use percent_encoding::percent_decode;
+use atcoder::{revel_deserialize, revel_serialize};
+
+/// The signature is not checked by the client, so any value will do.
+const SIGNATURE: &str = "fakesignature";
+
+pub const TASKS: &[(&str, &str)] = &[
+    ("practice_1", "A - Welcome to AtCoder"),
+    ("practice_2", "B - Interactive Sorting"),
+];
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("3003", "C++14 (GCC 5.4.1)"),
+    ("3504", "Rust (1.15.1)"),
+];
+
+#[derive(Clone, Debug)]
+pub struct FakeSubmission {
+    pub id: u64,
+    pub contest: String,
+    pub task: String,
+    pub user: String,
+    pub language: String,
+    pub source: String,
+}
+
+#[derive(Default)]
+struct State {
+    users: HashMap<String, String>,
+    registered: HashSet<(String, String)>,
+    submissions: Vec<FakeSubmission>,
+    next_token: u64,
+    requests: usize,
+}
+
+pub struct FakeAtCoder {
+    base_url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeAtCoder {
+    pub fn start() -> FakeAtCoder {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                handle(stream, &server_state);
+            }
+        });
+        FakeAtCoder { base_url, state }
+    }
+
+    pub fn base_url(&self) -> String {
+        self.base_url.clone()
+    }
+
+    pub fn add_user(&self, username: &str, password: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .users
+            .insert(username.to_owned(), password.to_owned());
+    }
+
+    pub fn submissions(&self) -> Vec<FakeSubmission> {
+        self.state.lock().unwrap().submissions.clone()
+    }
+
+    pub fn requests(&self) -> usize {
+        //! The number of requests served so far.
+        self.state.lock().unwrap().requests
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    /// From the `lang` query parameter or the `language` cookie.
+    lang: Option<String>,
+    session: HashMap<String, String>,
+    form: HashMap<String, String>,
+    if_none_match: Option<String>,
+}
+
+struct HttpResponse {
+    status: &'static str,
+    location: Option<String>,
+    session: HashMap<String, String>,
+    flash: Option<(&'static str, String)>,
+    /// Pages are sent with an `ETag`, and this or `no-cache`.
+    cache_control: Option<&'static str>,
+    etag: Option<String>,
+    body: String,
+}
+
+fn decode_component(s: &str) -> String {
+    let unplussed = s.replace('+', " ");
+    percent_decode(unplussed.as_bytes()).decode_utf8_lossy().into_owned()
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let target = parts.next()?;
+    let (path, query) = match target.find('?') {
+        Some(question) => (target[..question].to_owned(), &target[question + 1..]),
+        None => (target.to_owned(), ""),
+    };
+    let mut lang = query.split('&').find_map(|pair| pair.strip_prefix("lang=")).map(str::to_owned);
+
+    let mut length = 0;
+    let mut session = HashMap::new();
+    let mut if_none_match = None;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let colon = header.find(':')?;
+        let (name, value) = (header[..colon].to_lowercase(), header[colon + 1..].trim());
+        if name == "content-length" {
+            length = value.parse().ok()?;
+        } else if name == "if-none-match" {
+            if_none_match = Some(value.to_owned());
+        } else if name == "cookie" {
+            for pair in value.split("; ") {
+                if let Some(value) = pair.strip_prefix("REVEL_SESSION=") {
+                    let payload = &value[value.find('-')? + 1..];
+                    session = revel_deserialize::from_bytes(payload.as_bytes()).ok()?;
+                } else if let Some(value) = pair.strip_prefix("language=") {
+                    lang = lang.or_else(|| Some(value.to_owned()));
+                }
+            }
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    let form = String::from_utf8(body)
+        .ok()?
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let equal = pair.find('=').unwrap_or(pair.len());
+            (decode_component(&pair[..equal]), decode_component(pair[equal..].trim_start_matches('=')))
+        })
+        .collect();
+    Some(HttpRequest { method, path, lang, session, form, if_none_match })
+}
+
+fn write_response(stream: &mut TcpStream, response: HttpResponse) {
+    let session = format!(
+        "{}-{}",
+        SIGNATURE,
+        revel_serialize::to_string(&response.session).unwrap()
+    );
+    let flash = match response.flash {
+        Some((kind, ref message)) => {
+            let mut fields = BTreeMap::new();
+            fields.insert(kind, message);
+            format!("{}; Path=/", revel_serialize::to_string(&fields).unwrap())
+        }
+        None => "; Path=/; Max-Age=0".to_owned(),
+    };
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\nSet-Cookie: REVEL_SESSION={}; Path=/\r\nSet-Cookie: REVEL_FLASH={}\r\n",
+        response.status,
+        response.body.len(),
+        session,
+        flash
+    );
+    if let Some(location) = response.location {
+        head.push_str(&format!("Location: {}\r\n", location));
+    }
+    if let Some(ref etag) = response.etag {
+        let cache_control = response.cache_control.unwrap_or("no-cache");
+        head.push_str(&format!("Cache-Control: private, {}\r\nETag: {}\r\n", cache_control, etag));
+    }
+    head.push_str("\r\n");
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+}
+
+fn etag(body: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+fn page(session: &HashMap<String, String>, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><body><form method=\"POST\">{}\
+         <input type=\"hidden\" name=\"csrf_token\" value=\"{}\"></form></body></html>",
+        content,
+        session["csrf_token"]
+    )
+}
+
+fn submit_form() -> String {
+    let mut content = "<select id=\"select-task\">".to_owned();
+    for &(id, name) in TASKS {
+        content.push_str(&format!("<option value=\"{}\">{}</option>", id, name));
+    }
+    content.push_str("</select>");
+    for &(task, _) in TASKS {
+        content.push_str(&format!("<select id=\"select-lang-{}\">", task));
+        for &(id, name) in LANGUAGES {
+            content.push_str(&format!("<option value=\"{}\">{}</option>", id, name));
+        }
+        content.push_str("</select>");
+    }
+    content
+}
+
+fn submissions_table(submissions: &[FakeSubmission]) -> String {
+    let mut content = "<table><thead><tr><th>Submission Time</th></tr></thead><tbody>".to_owned();
+    for submission in submissions {
+        let task = TASKS.iter().find(|t| t.0 == submission.task).unwrap().1;
+        let language = LANGUAGES.iter().find(|l| l.0 == submission.language).unwrap().1;
+        content.push_str(&format!(
+            "<tr><td>2017-11-05 21:01:52+0900</td><td><a>{}</a></td><td><a>{}</a></td><td>{}</td>\
+             <td>100</td><td>{} Byte</td><td><span class=\"label\">AC</span></td><td>2 ms</td>\
+             <td>4352 KB</td><td><a href=\"/contests/{}/submissions/{}\">Detail</a></td></tr>",
+            task,
+            submission.user,
+            language,
+            submission.source.len(),
+            submission.contest,
+            submission.id
+        ));
+    }
+    content.push_str("</tbody></table>");
+    content
+}
+
+fn respond(request: HttpRequest, state: &mut State) -> HttpResponse {
+    let mut session = request.session;
+    if !session.contains_key("csrf_token") {
+        state.next_token += 1;
+        session.insert("csrf_token".to_owned(), format!("token{}", state.next_token));
+    }
+    session.insert("_TS".to_owned(), "4102444800".to_owned());
+    let user = session.get("UserScreenName").cloned();
+    let ja = request.lang.as_deref() == Some("ja");
+    let if_none_match = request.if_none_match;
+    let message = |en: &str, ja_message: &str| if ja { ja_message } else { en }.to_owned();
+
+    let mut response = HttpResponse {
+        status: "200 OK",
+        location: None,
+        session: HashMap::new(),
+        flash: None,
+        cache_control: None,
+        etag: None,
+        body: String::new(),
+    };
+    let redirect = |response: &mut HttpResponse, location: &str| {
+        response.status = "302 Found";
+        response.location = Some(location.to_owned());
+    };
+
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let contest = if segments.len() >= 2 && segments[0] == "contests" {
+        Some(segments[1].to_owned())
+    } else {
+        None
+    };
+    let post = request.method == "POST";
+    if post && request.form.get("csrf_token") != session.get("csrf_token") {
+        response.status = "403 Forbidden";
+    } else if user.is_none() && contest.is_some() && (post || segments.len() > 2) {
+        redirect(&mut response, &format!("/login?continue={}", request.path));
+    } else {
+        match (post, &*segments) {
+            (false, &["login"]) | (false, &[""]) => {
+                response.body = page(&session, "");
+            }
+            (false, &["contests", _]) => {
+                response.cache_control = Some("max-age=60");
+                response.body = page(&session, "");
+            }
+            (true, &["login"]) => {
+                let username = &request.form["username"];
+                if state.users.get(username) == Some(&request.form["password"]) {
+                    session.insert("UserScreenName".to_owned(), username.clone());
+                    response.flash = Some((
+                        "success",
+                        if ja { format!("ようこそ、{} さん。", username) } else { format!("Welcome, {}.", username) },
+                    ));
+                    redirect(&mut response, "/");
+                } else {
+                    response.flash = Some((
+                        "error",
+                        message("Username or Password is incorrect.", "ユーザ名またはパスワードが正しくありません。"),
+                    ));
+                    redirect(&mut response, "/login");
+                }
+            }
+            (true, &["logout"]) => {
+                session.remove("UserScreenName");
+                response.flash = Some(("success", message("Signed out.", "ログアウトしました。")));
+                redirect(&mut response, "/");
+            }
+            (true, &["contests", contest, "register"]) => {
+                let key = (contest.to_owned(), user.clone().unwrap());
+                if state.registered.insert(key) {
+                    response.flash = Some(("success", message("You have been registered.", "参加登録しました。")));
+                } else {
+                    response.flash = Some(("error", message("You are already registered.", "既に参加登録済みです。")));
+                }
+                redirect(&mut response, &format!("/contests/{}/", contest));
+            }
+            (false, &["contests", _, "submit"]) => {
+                response.body = page(&session, &submit_form());
+            }
+            (true, &["contests", contest, "submit"]) => {
+                state.submissions.push(FakeSubmission {
+                    id: state.submissions.len() as u64 + 1,
+                    contest: contest.to_owned(),
+                    task: request.form["data.TaskScreenName"].clone(),
+                    user: user.clone().unwrap(),
+                    language: request.form["data.LanguageId"].clone(),
+                    source: request.form["sourceCode"].clone(),
+                });
+                redirect(&mut response, &format!("/contests/{}/submissions/me", contest));
+            }
+            (false, &["contests", contest, "submissions", "me"]) => {
+                let own: Vec<_> = state
+                    .submissions
+                    .iter()
+                    .filter(|s| s.contest == contest && Some(&s.user) == user.as_ref())
+                    .cloned()
+                    .collect();
+                response.body = page(&session, &submissions_table(&own));
+            }
+            _ => response.status = "404 Not Found",
+        }
+    }
+    if !post && response.status == "200 OK" {
+        let etag = etag(&response.body);
+        if if_none_match.as_ref() == Some(&etag) {
+            response.status = "304 Not Modified";
+            response.body.clear();
+        }
+        response.etag = Some(etag);
+    }
+    response.session = session;
+    response
+}
+
+fn handle(mut stream: TcpStream, state: &Mutex<State>) {
+    if let Some(request) = read_request(&mut stream) {
+        let mut state = state.lock().unwrap();
+        state.requests += 1;
+        let response = respond(request, &mut state);
+        write_response(&mut stream, response);
+    }
+}
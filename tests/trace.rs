@@ -0,0 +1,59 @@
+use std::sync::{Arc, Mutex};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use reqwest::{StatusCode, Url};
+use atcoder::trace::TraceTransport;
+use atcoder::transport::{MemoryTransport, Method, Request, Response, Transport};
+
+const URL: &str = "https://atcoder.test/login/";
+
+/// Keeps every message logged by the crate.
+struct Capture(Mutex<Vec<(Level, String)>>);
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target().starts_with("atcoder") {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[tokio::test]
+async fn test_trace() {
+    let capture: &'static Capture = Box::leak(Box::new(Capture(Mutex::new(vec![]))));
+    log::set_logger(capture).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let memory = Arc::new(MemoryTransport::new());
+    memory.push(
+        Method::Post,
+        URL,
+        Response::new(StatusCode::FOUND)
+            .with_header("Location", "/home")
+            .with_header("Set-Cookie", "REVEL_SESSION=secret; Path=/")
+            .with_header("Set-Cookie", "REVEL_FLASH=%00success%3AWelcome%00; Path=/"),
+    );
+    let trace = TraceTransport::new(memory);
+    let request = Request {
+        method: Method::Post,
+        url: Url::parse(URL).unwrap(),
+        headers: vec![("Cookie".to_owned(), "REVEL_SESSION=old".to_owned())],
+        body: Some("username=chokudai&password=hunter2".to_owned()),
+    };
+    trace.send(request).await.unwrap();
+
+    let messages = capture.0.lock().unwrap();
+    let info: Vec<_> = messages.iter().filter(|(level, _)| *level == Level::Info).collect();
+    assert_eq!(info.len(), 1);
+    assert!(info[0].1.starts_with("POST https://atcoder.test/login/ 302 Found to /home in "));
+    assert!(info[0].1.ends_with("set cookies [\"REVEL_SESSION\", \"REVEL_FLASH\"]"));
+    for (_, message) in messages.iter() {
+        assert!(!message.contains("secret") && !message.contains("old"));
+        assert!(!message.contains("hunter2"));
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::sync::Arc;
+use reqwest::StatusCode;
+use atcoder::{create_client_with_transport, Client, Config, ErrorKind, Session};
+use atcoder::locale::Locale;
+use atcoder::revel_serialize;
+use atcoder::transport::{MemoryTransport, Method, Response};
+
+const BASE: &str = "https://atcoder.test";
+
+fn session_cookie(user: &str) -> String {
+    let mut fields = BTreeMap::new();
+    fields.insert("UserScreenName", user);
+    fields.insert("_TS", "1510000000");
+    format!("REVEL_SESSION=0a1b2c-{}; Path=/", revel_serialize::to_string(&fields).unwrap())
+}
+
+fn flash_cookie(kind: &str, message: &str) -> String {
+    let mut fields = BTreeMap::new();
+    fields.insert(kind, message);
+    format!("REVEL_FLASH={}; Path=/", revel_serialize::to_string(&fields).unwrap())
+}
+
+fn page(user: &str, body: &str) -> Response {
+    Response::new(StatusCode::OK)
+        .with_header("Set-Cookie", &session_cookie(user))
+        .with_body(format!(
+            "<html><body><form>{}<input type=\"hidden\" name=\"csrf_token\" value=\"token\">\
+             </form></body></html>",
+            body
+        ))
+}
+
+fn redirect(user: &str, flash: Option<(&str, &str)>) -> Response {
+    let mut response = Response::new(StatusCode::FOUND)
+        .with_header("Location", "/")
+        .with_header("Set-Cookie", &session_cookie(user));
+    if let Some((kind, message)) = flash {
+        response = response.with_header("Set-Cookie", &flash_cookie(kind, message));
+    }
+    response
+}
+
+fn setup() -> (Arc<MemoryTransport>, Client) {
+    let transport = Arc::new(MemoryTransport::new());
+    let config = Config { base_url: BASE.to_owned(), ..Config::default() };
+    let client = create_client_with_transport(config, transport.clone());
+    (transport, client)
+}
+
+fn expect_login(transport: &MemoryTransport) {
+    transport.push(Method::Get, &format!("{}/login/", BASE), page("", ""));
+    transport.push(
+        Method::Post,
+        &format!("{}/login/", BASE),
+        redirect("chokudai", Some(("success", "Welcome"))),
+    );
+}
+
+async fn login(client: &Client) -> Session {
+    Session::login("chokudai", "p@ss word", client).await.unwrap().0
+}
+
+#[test]
+fn test_url() {
+    let (_, client) = setup();
+    assert_eq!(client.url("/contests/abc001/"), "https://atcoder.test/contests/abc001/");
+    let config = Config {
+        base_url: BASE.to_owned(),
+        locale: Some(Locale::Japanese),
+        ..Config::default()
+    };
+    let client = create_client_with_transport(config, Arc::new(MemoryTransport::new()));
+    assert_eq!(
+        client.url("/contests/abc001/submissions?f.Language=3504"),
+        "https://atcoder.test/contests/abc001/submissions?f.Language=3504&lang=ja"
+    );
+}
+
+#[tokio::test]
+async fn test_login() {
+    let (transport, client) = setup();
+    expect_login(&transport);
+    let (session, message) = Session::login("chokudai", "p@ss word", &client).await.unwrap();
+    assert_eq!(message.unwrap().text, "Welcome");
+    let info = session.authentication().unwrap().session_info().unwrap();
+    assert_eq!(info.username, Some("chokudai".to_owned()));
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].header("Cookie"), None);
+    let post = &requests[1];
+    assert_eq!(post.method, Method::Post);
+    assert!(post.header("Cookie").unwrap().starts_with("REVEL_SESSION=0a1b2c-"));
+    assert_eq!(
+        post.body,
+        Some("username=chokudai&password=p%40ss+word&csrf_token=token".to_owned())
+    );
+}
+
+#[tokio::test]
+async fn test_login_failure() {
+    let (transport, client) = setup();
+    transport.push(Method::Get, &format!("{}/login/", BASE), page("", ""));
+    transport.push(
+        Method::Post,
+        &format!("{}/login/", BASE),
+        redirect("", Some(("error", "Wrong password"))),
+    );
+    let error = Session::login("chokudai", "wrong", &client).await.err().unwrap();
+    match *error.kind() {
+        ErrorKind::Unauthorized(ref flash) => assert_eq!(flash.text, "Wrong password"),
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_join() {
+    let (transport, client) = setup();
+    expect_login(&transport);
+    let session = login(&client).await;
+    transport.push(
+        Method::Get,
+        &format!("{}/contests/abc001/", BASE),
+        page("chokudai", ""),
+    );
+    transport.push(
+        Method::Post,
+        &format!("{}/contests/abc001/register/", BASE),
+        redirect("chokudai", Some(("success", "Registered"))),
+    );
+    assert_eq!(session.join(&"abc001".parse().unwrap()).await.unwrap().unwrap().text, "Registered");
+}
+
+#[tokio::test]
+async fn test_post_session_expired() {
+    let (transport, client) = setup();
+    expect_login(&transport);
+    let session = login(&client).await;
+    let contest = "abc001".parse().unwrap();
+    let register = format!("{}/contests/abc001/register/", BASE);
+    let expired = Response::new(StatusCode::FOUND).with_header("Location", "/login/");
+    transport.push(Method::Get, &format!("{}/contests/abc001/", BASE), page("chokudai", ""));
+    transport.push(Method::Post, &register, expired.clone());
+    transport.push(Method::Post, &register, expired);
+    transport.push(Method::Post, &register, redirect("chokudai", Some(("success", "Registered"))));
+    match *session.join(&contest).await.err().unwrap().kind() {
+        ErrorKind::SessionExpired => {}
+        ref other => panic!("unexpected error: {}", other),
+    }
+
+    let session = session.with_credentials(|| Ok(("chokudai".to_owned(), "p@ss word".to_owned())));
+    assert_eq!(session.join(&contest).await.unwrap().unwrap().text, "Registered");
+    let posts: Vec<_> = transport
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == Method::Post)
+        .map(|r| r.url.path().to_owned())
+        .collect();
+    assert_eq!(posts, [
+        "/login/",
+        "/contests/abc001/register/",
+        "/contests/abc001/register/",
+        "/login/",
+        "/contests/abc001/register/",
+    ]);
+}
+
+#[tokio::test]
+async fn test_submit() {
+    let (transport, client) = setup();
+    expect_login(&transport);
+    let session = login(&client).await;
+    let submit = format!("{}/contests/abc001/submit/", BASE);
+    transport.push(
+        Method::Get,
+        &submit,
+        page(
+            "chokudai",
+            "<select id=\"select-task\">\
+             <option value=\"abc001_1\">A - 積雪深差</option>\
+             <option value=\"abc001_2\">B - 視程の通報</option>\
+             </select>\
+             <select id=\"select-lang-abc001_2\">\
+             <option value=\"3003\">C++14 (GCC 5.4.1)</option>\
+             <option value=\"3504\">Rust (1.15.1)</option>\
+             </select>",
+        ),
+    );
+    let contest = "abc001".parse().unwrap();
+    let (task, lang) = session.resolve_submit(&contest, "b", "rust").await.unwrap();
+    let unknown = "9999".parse().unwrap();
+    match *session.submit(&contest, &task, &unknown, String::new()).await.err().unwrap().kind() {
+        ErrorKind::NoSuchLanguage => {}
+        ref other => panic!("unexpected error: {}", other),
+    }
+    transport.push(Method::Post, &submit, redirect("chokudai", None));
+    session.submit(&contest, &task, &lang, "fn main() {}".to_owned()).await.unwrap();
+    let post = transport.requests().pop().unwrap();
+    assert_eq!(
+        post.body,
+        Some(
+            "data.TaskScreenName=abc001_2&data.LanguageId=3504&sourceCode=fn+main%28%29+%7B%7D\
+             &csrf_token=token"
+                .to_owned()
+        )
+    );
+}
+
+const SUBMISSIONS: &str = "<table><thead><tr><th>Submission Time</th></tr></thead><tbody>\
+    <tr>\
+    <td>2017-11-05 21:01:52+0900</td>\
+    <td><a href=\"/contests/abc001/tasks/abc001_2\">B - 視程の通報</a></td>\
+    <td><a href=\"/users/chokudai\">chokudai</a></td>\
+    <td>Rust (1.15.1)</td>\
+    <td>100</td>\
+    <td>412 Byte</td>\
+    <td><span class=\"label label-success\">AC</span></td>\
+    <td>2 ms</td>\
+    <td>4352 KB</td>\
+    <td><a href=\"/contests/abc001/submissions/1713284\">Detail</a></td>\
+    </tr>\
+    </tbody></table>";
+
+#[tokio::test]
+async fn test_submissions() {
+    let (transport, client) = setup();
+    expect_login(&transport);
+    let session = login(&client).await;
+    transport.push(
+        Method::Get,
+        &format!("{}/contests/abc001/submissions/me/", BASE),
+        page("chokudai", SUBMISSIONS),
+    );
+    let submissions = session.submissions(&"abc001".parse().unwrap()).await.unwrap();
+    assert_eq!(submissions.len(), 1);
+    let submission = &submissions[0];
+    assert_eq!(submission.id.as_str(), "1713284");
+    assert_eq!(submission.task, "B - 視程の通報");
+    assert_eq!(submission.code_length, 412);
+    assert_eq!(submission.status.to_string(), "Pass");
+    assert_eq!(submission.time, Some(2));
+    assert_eq!(submission.memory, Some(4352));
+}
+
+#[tokio::test]
+async fn test_session_expired() {
+    let (transport, client) = setup();
+    expect_login(&transport);
+    let session = login(&client).await;
+    let url = format!("{}/contests/abc001/submissions/me/", BASE);
+    let expired = Response::new(StatusCode::FOUND)
+        .with_header("Location", "/login?continue=%2Fcontests%2Fabc001%2Fsubmissions%2Fme%2F");
+    transport.push(Method::Get, &url, expired.clone());
+    match *session.submissions(&"abc001".parse().unwrap()).await.err().unwrap().kind() {
+        ErrorKind::SessionExpired => {}
+        ref other => panic!("unexpected error: {}", other),
+    }
+
+    let session = session.with_credentials(|| Ok(("chokudai".to_owned(), "p@ss word".to_owned())));
+    transport.push(Method::Get, &url, page("chokudai", SUBMISSIONS));
+    assert_eq!(session.submissions(&"abc001".parse().unwrap()).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_dump_html() {
+    let transport = Arc::new(MemoryTransport::new());
+    let config = Config { base_url: BASE.to_owned(), dump_html: true, ..Config::default() };
+    let client = create_client_with_transport(config, transport.clone());
+    expect_login(&transport);
+    let session = login(&client).await;
+    let url = format!("{}/contests/abc001/submissions/me/", BASE);
+    transport.push(Method::Get, &url, page("chokudai", "<p>Under maintenance</p>"));
+    let error = match session.submissions(&"abc001".parse().unwrap()).await.err().unwrap().kind() {
+        ErrorKind::Scrape(e) => e.clone(),
+        other => panic!("unexpected error: {}", other),
+    };
+    assert_eq!(error.url, Some(url));
+    let dump = error.dump.unwrap();
+    let mut html = String::new();
+    File::open(&dump).unwrap().read_to_string(&mut html).unwrap();
+    assert!(html.contains("Under maintenance"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&dump).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    fs::remove_file(&dump).unwrap();
+}
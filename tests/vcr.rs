@@ -0,0 +1,145 @@
+mod support;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use atcoder::{create_client, Config, ErrorKind, Mode, Session};
+use atcoder::cache::Cache;
+use atcoder::locale::Flash;
+use support::FakeAtCoder;
+
+const PRACTICE_A: &str = include_str!("submit_data/practice_a.rs");
+
+async fn replay(path: &Path) -> (Session, Option<Flash>) {
+    let config = Config {
+        base_url: "https://atcoder.jp".to_owned(),
+        mode: Mode::Replay(path.to_owned()),
+        ..Config::default()
+    };
+    let client = create_client(config).unwrap();
+    Session::login("chokudai", "password", &client).await.unwrap()
+}
+
+fn cassette(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cassettes").join(name)
+}
+
+/// Replays a hand-written cassette. Its pages are minimal stand-ins in the
+/// shape of AtCoder's, not a capture, so this checks the replay machinery
+/// rather than the parsers against the real site.
+#[tokio::test]
+async fn test_replay_synthetic() {
+    let (session, message) = replay(&cassette("synthetic_practice.json")).await;
+    assert_eq!(message.unwrap().text, "ようこそ、chokudai さん。");
+    let joined = session.join(&"practice".parse().unwrap()).await.unwrap().unwrap();
+    assert_eq!(joined.text, "参加登録しました。");
+    session.submit(
+        &"practice".parse().unwrap(),
+        &"practice_1".parse().unwrap(),
+        &"3504".parse().unwrap(),
+        PRACTICE_A.to_owned(),
+    ).await.unwrap();
+    let submissions = session.submissions(&"practice".parse().unwrap()).await.unwrap();
+    assert_eq!(submissions.len(), 1);
+    assert_eq!(submissions[0].id.as_str(), "1749520");
+    assert_eq!(submissions[0].task, "A - Welcome to AtCoder");
+    assert_eq!(submissions[0].status.to_string(), "Pass");
+}
+
+#[tokio::test]
+async fn test_replay_mismatch() {
+    let (session, _) = replay(&cassette("synthetic_practice.json")).await;
+    match *session.submissions(&"practice".parse().unwrap()).await.err().unwrap().kind() {
+        ErrorKind::CassetteMismatch(_) => {}
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_record() {
+    let server = FakeAtCoder::start();
+    server.add_user("chokudai", "hunter2");
+    let path = env::temp_dir().join(format!("atcoder-vcr-{}.json", process::id()));
+
+    let config = Config {
+        base_url: server.base_url(),
+        mode: Mode::Record(path.clone()),
+        ..Config::default()
+    };
+    let client = create_client(config).unwrap();
+    let (session, _) = Session::login("chokudai", "hunter2", &client).await.unwrap();
+    session.join(&"practice".parse().unwrap()).await.unwrap();
+    let recorded = session.submissions(&"practice".parse().unwrap()).await.unwrap();
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert!(!contents.contains("hunter2"));
+    assert!(!contents.contains("UserScreenName"));
+    assert!(contents.contains("REVEL_SESSION=SCRUBBED"));
+
+    let requests = server.requests();
+    let config = Config {
+        base_url: server.base_url(),
+        mode: Mode::Replay(path.clone()),
+        ..Config::default()
+    };
+    let client = create_client(config).unwrap();
+    let (session, message) = Session::login("chokudai", "hunter2", &client).await.unwrap();
+    assert_eq!(message.unwrap().text, "Welcome, chokudai.");
+    session.join(&"practice".parse().unwrap()).await.unwrap();
+    assert_eq!(session.submissions(&"practice".parse().unwrap()).await.unwrap(), recorded);
+    assert_eq!(server.requests(), requests);
+    fs::remove_file(&path).unwrap();
+}
+
+fn assert_already_registered(kind: &ErrorKind) {
+    match *kind {
+        ErrorKind::Unauthorized(ref flash) => assert_eq!(flash.text, "You are already registered."),
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+/// Pages served by the cache, fresh or revalidated, must end up in the
+/// cassette as full responses, since replaying builds no cache.
+#[tokio::test]
+async fn test_record_cached() {
+    let server = FakeAtCoder::start();
+    server.add_user("chokudai", "hunter2");
+    let path = env::temp_dir().join(format!("atcoder-vcr-cached-{}.json", process::id()));
+    let dir = env::temp_dir().join(format!("atcoder-vcr-cache-{}", process::id()));
+    fs::remove_dir_all(&dir).ok();
+    let contest = "practice".parse().unwrap();
+
+    let config = Config {
+        base_url: server.base_url(),
+        mode: Mode::Record(path.clone()),
+        cache: Some(Cache::new(&dir)),
+        ..Config::default()
+    };
+    let client = create_client(config).unwrap();
+    let (session, _) = Session::login("chokudai", "hunter2", &client).await.unwrap();
+    session.join(&contest).await.unwrap();
+    let before = server.requests();
+    // The contest page is still fresh, so only the registration is sent.
+    assert_already_registered(session.join(&contest).await.err().unwrap().kind());
+    assert_eq!(server.requests(), before + 1);
+    // The list is revalidated, and comes back unchanged.
+    let recorded = session.submissions(&contest).await.unwrap();
+    assert_eq!(session.submissions(&contest).await.unwrap(), recorded);
+
+    let config = Config {
+        base_url: server.base_url(),
+        mode: Mode::Replay(path.clone()),
+        ..Config::default()
+    };
+    let client = create_client(config).unwrap();
+    let (session, _) = Session::login("chokudai", "hunter2", &client).await.unwrap();
+    session.join(&contest).await.unwrap();
+    assert_already_registered(session.join(&contest).await.err().unwrap().kind());
+    assert_eq!(session.submissions(&contest).await.unwrap(), recorded);
+    assert_eq!(session.submissions(&contest).await.unwrap(), recorded);
+    fs::remove_file(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}